@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
@@ -8,7 +8,45 @@ use crate::poem::Poem;
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Book {
     pub nn: u32,
-    pub poems: HashMap<u32, Poem>,
+    pub poems: BTreeMap<u32, Poem>,
+    /// Стихотворения, для которых нашлись не все языки
+    #[serde(default)]
+    pub incomplete: BTreeMap<u32, Poem>,
+    /// Текст вне стихотворений в порядке исходного файла
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<Block>,
+}
+
+/// Вид текста вне стихотворений, см. `block_classes` в конфигурации
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockKind {
+    /// Заглавие, посвящение и т.п. в начале тома
+    FrontMatter,
+    SectionHeading,
+    Footnote,
+}
+
+/// Текст вне стихотворений: подряд идущие абзацы одного вида
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Block {
+    pub kind: BlockKind,
+    /// Язык по письменности текста, если её удалось определить
+    pub lang: Option<String>,
+    /// Номер стихотворения, перед которым стоит блок; нет - в конце тома
+    pub before: Option<u32>,
+    /// Номер стихотворения, за которым стоит сноска; задан - `before` не используется
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<u32>,
+    pub lines: Vec<String>,
+}
+
+/// Блоки тома по месту вывода; ключи - номера стихотворений строкой ("6001" - для шаблонов)
+#[derive(Serialize, Default)]
+pub struct PlacedBlocks<'a> {
+    pub before: BTreeMap<String, Vec<&'a Block>>,
+    pub after: BTreeMap<String, Vec<&'a Block>>,
+    pub end: Vec<&'a Block>,
 }
 
 impl Book {
@@ -16,6 +54,8 @@ impl Book {
         Self {
             nn,
             poems: Default::default(),
+            incomplete: Default::default(),
+            blocks: Default::default(),
         }
     }
 
@@ -23,8 +63,58 @@ impl Book {
         self.poems.insert(p.nn, p);
     }
 
+    pub fn add_incomplete(&mut self, p: Poem) {
+        self.incomplete.insert(p.nn, p);
+    }
+
+    /// Наименьший и наибольший номер стихотворения в томе (включая неполные)
+    pub fn range(&self) -> Option<(u32, u32)> {
+        let first = self.poems.keys().chain(self.incomplete.keys()).min()?;
+        let last = self.poems.keys().chain(self.incomplete.keys()).max()?;
+        Some((*first, *last))
+    }
+
+    /// Доля полностью переведённых стихотворений, в процентах
+    pub fn completeness(&self) -> f64 {
+        let total = self.poems.len() + self.incomplete.len();
+        if total == 0 {
+            return 0.0;
+        }
+        self.poems.len() as f64 * 100.0 / total as f64
+    }
+
+    /// Номера всех стихотворений тома, включая неполные
+    pub fn numbers(&self) -> BTreeSet<u32> {
+        self.poems.keys().chain(self.incomplete.keys()).copied().collect()
+    }
+
+    /// Где выводится блок: (номер стихотворения, после него ли); нет - в конце тома.
+    /// Блок у номера, который не стал стихотворением, переходит к следующему стихотворению
+    pub fn place(block: &Block, numbers: &BTreeSet<u32>) -> Option<(u32, bool)> {
+        if let Some(nn) = block.after.filter(|nn| numbers.contains(nn)) {
+            return Some((nn, true));
+        }
+        let nn = block.after.or(block.before)?;
+        numbers.range(nn..).next().map(|next| (*next, false))
+    }
+
+    /// Блоки перед стихотворениями, после них и в конце тома
+    pub fn blocks_by_poem(&self) -> PlacedBlocks<'_> {
+        let numbers = self.numbers();
+        let mut res = PlacedBlocks::default();
+        for block in &self.blocks {
+            match Book::place(block, &numbers) {
+                Some((nn, false)) => res.before.entry(nn.to_string()).or_default().push(block),
+                Some((nn, true)) => res.after.entry(nn.to_string()).or_default().push(block),
+                None => res.end.push(block),
+            }
+        }
+        res
+    }
+
+    /// Все стихотворения тома по номерам, включая неполные
     pub fn get_ordered_poems(&self) -> Vec<&Poem> {
-        let mut vec = self.poems.values().collect_vec();
+        let mut vec = self.poems.values().chain(self.incomplete.values()).collect_vec();
         vec.sort_by_key(|p| { &p.nn });
         // self.poems.values().into_iter().sorted_by(|a, b| Ord::cmp(&b.nn, &a.nn)).
         vec
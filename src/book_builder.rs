@@ -1,26 +1,49 @@
 use std::collections::HashMap;
-use std::ops::Deref;
-use crate::{error::*, utils};
+
+use indexmap::IndexMap;
+use itertools::Itertools;
+
+use crate::config::CONFIG;
+use crate::utils;
 use crate::report::BookReport;
 use crate::{Result, Error};
-use crate::book::Book;
+use crate::book::{Block, BlockKind, Book};
 use crate::poem::Poem;
+use crate::script;
 use crate::tmp_poem::TmpPoem;
 
 pub struct BookBuilder {
+    languages: Vec<String>,
     tmp_poem: Option<TmpPoem>,
-    tmp_poems: HashMap<u32, TmpPoem>,
+    tmp_poems: HashMap<u32, IndexMap<String, Vec<String>>>,
+    /// Названия собираемых стихотворений: номер -> язык -> название
+    tmp_titles: HashMap<u32, IndexMap<String, String>>,
     book: Book,
-    report: BookReport
+    report: BookReport,
+    /// Блоки, для которых ещё не встретился следующий номер стихотворения
+    tmp_blocks: Vec<Block>,
+    /// Последним был абзац блока: следующий абзац того же вида продолжает его
+    in_block: bool,
+    /// После абзаца с неверным номером: строки до следующего номера пропускаются
+    skip_lines: bool,
 }
 
 impl BookBuilder {
     pub fn new(nn: u32) -> Self {
+        Self::with_languages(nn, CONFIG.languages.clone())
+    }
+
+    pub fn with_languages(nn: u32, languages: Vec<String>) -> Self {
         Self {
+            languages,
             tmp_poem: None,
             tmp_poems: Default::default(),
+            tmp_titles: Default::default(),
             book: Book::new(nn),
             report: BookReport::new(nn),
+            tmp_blocks: Vec::new(),
+            in_block: false,
+            skip_lines: false,
         }
     }
 
@@ -28,6 +51,7 @@ impl BookBuilder {
         self.report.add(error);
     }
 
+    #[allow(dead_code)]
     pub fn check_if_error<F>(&mut self, f: F) -> Result<()>
     where 
         F: Fn()->Result<()>,
@@ -38,46 +62,43 @@ impl BookBuilder {
         Ok(())
     }
 
-    pub fn parse_poem_num(&mut self, str: &str) -> u32 {
-        let res = utils::parse_poem_num_impl(str);
-        res.unwrap_or_else(|e| {self.report.add(&e); 0})
+    /// Обработка абзаца номера: "06,039." или "06,039. (Название)". Неверный номер - ошибка,
+    /// и строки до следующего номера пропускаются, а не достаются соседнему стихотворению
+    pub fn proc_number_text(&mut self, text: &str) {
+        match utils::parse_poem_num_impl(text) {
+            Ok(num) => {
+                self.proc_number(num.nn);
+                if let Some(p) = self.tmp_poem.as_mut() {
+                    p.title = num.title;
+                }
+            }
+            Err(e) => {
+                self.report.add(&e);
+                if let Some(tmp_poem) = self.tmp_poem.take() {
+                    self.close_tmp_poem(tmp_poem);
+                }
+                self.skip_lines = true;
+            }
+        }
     }
 
     /// Обработка строчки с номером (закрытие текущего, открытие нового с новым номером)
     pub fn proc_number(&mut self, new_nn: u32) {
-        let tmp_poem = self.tmp_poem.take();
-        match tmp_poem {
-            // Закрываем временный объект и открываем новый с новым номером
-            Some(tmp_poem) => {
-                if self.book.poems.contains_key(&tmp_poem.nn) {
-                    // Ошибка, две поэмы с одним номером
-                    self.report.add(&Error::DuplicatePoem{number: tmp_poem.nn});  
-                }
-                let en_poem = self.tmp_poems.remove(&tmp_poem.nn);
-                match en_poem {
-                    // Завершен русский перевод, добавляем полностью готовое стихотворенье 
-                    Some(en) => {
-                        let p = Poem::new(tmp_poem.nn, en.lines, tmp_poem.lines);
-                        self.book.add(p)
-                    }
-                    // Завершаем английскую часть стихотворенья
-                    None => {
-                        self.tmp_poems.insert(tmp_poem.nn, tmp_poem);
-                    }
-                }
-                // открываем новый с новым номером
-                self.tmp_poem = Some(TmpPoem { nn: new_nn, lines: vec![] })
-            }
-            // В самом начале нечего закрывать открываем новый
-            None => {
-                self.tmp_poem = Some(TmpPoem { nn: new_nn, lines: vec![] })
-            }
+        // Закрываем временный объект (если он есть) и открываем новый с новым номером
+        if let Some(tmp_poem) = self.tmp_poem.take() {
+            self.close_tmp_poem(tmp_poem);
         }
-        // Ok(())
+        self.close_tmp_blocks(Some(new_nn));
+        self.skip_lines = false;
+        self.tmp_poem = Some(TmpPoem::new(new_nn))
     }
 
     /// Обработка строки стихотворения  
     pub fn proc_line(&mut self, line: String) {
+        self.in_block = false;
+        if self.skip_lines {
+            return;
+        }
         let poem = self.tmp_poem.as_mut();
         match poem {
             Some( p) => {
@@ -87,41 +108,153 @@ impl BookBuilder {
         }
     }
 
-    /// Завершение обработки книги. Финализация модели книги.
-    pub fn build(mut self) -> (Option<Book>, BookReport) {
-        let tmp_poem = self.tmp_poem.take();
-        match tmp_poem {
-            // 
-            Some(tmp_poem) => {
-                if self.book.poems.contains_key(&tmp_poem.nn) {
-                    self.report.add(&Error::DuplicatePoem{number: tmp_poem.nn});
-
-                    return (Some(self.book), self.report)
-                    // (None, self.report)
-                }
-                let en_poem = self.tmp_poems.remove(&tmp_poem.nn);
-                match en_poem {
-                    Some(en) => {
-                        let p = Poem::new(tmp_poem.nn, en.lines, tmp_poem.lines);
-                        self.book.add(p);
+    /// Обработка строки стихотворения, язык которой задан маркером
+    pub fn proc_lang_line(&mut self, lang: &str, line: String) {
+        if let Some(p) = self.tmp_poem.as_mut() {
+            p.lang.get_or_insert_with(|| lang.to_string());
+        }
+        self.proc_line(line);
+    }
+
+    /// Обработка абзаца вне стихотворений
+    pub fn proc_block(&mut self, kind: BlockKind, line: String) {
+        match self.tmp_blocks.last_mut() {
+            Some(block) if self.in_block && block.kind == kind => block.lines.push(line),
+            _ => {
+                // Сноска относится к стихотворению, раздел которого она завершает
+                let after = match kind {
+                    BlockKind::Footnote => self.tmp_poem.as_ref().map(|p| p.nn),
+                    _ => None,
+                };
+                self.tmp_blocks.push(Block { kind, lang: None, before: None, after, lines: vec![line] })
+            }
+        }
+        self.in_block = true;
+    }
+
+    /// Абзац класса, который не разбирается: пропускается, но попадает в отчёт
+    pub fn proc_unknown_class(&mut self, class: &str) {
+        self.report.add_unknown_class(class);
+    }
 
-                        return (Some(self.book), self.report)
+    /// Готовые блоки (например, из JSON)
+    pub fn proc_blocks(&mut self, blocks: Vec<Block>) {
+        self.book.blocks.extend(blocks);
+    }
+
+    /// Блоки перед стихотворением `before` (нет - в конце тома) переходят в книгу
+    fn close_tmp_blocks(&mut self, before: Option<u32>) {
+        self.in_block = false;
+        for mut block in self.tmp_blocks.drain(..) {
+            let detected = script::detect(&block.lines);
+            block.lang = self.languages.iter()
+                .find(|l| detected.is_some() && script::language_script(l) == detected)
+                .cloned();
+            if block.after.is_none() {
+                block.before = before;
+            }
+            self.book.blocks.push(block);
+        }
+    }
+
+    /// Обработка готового раздела (например, из JSON): номер, язык и все его строки
+    pub fn proc_section(&mut self, nn: u32, lang: String, title: Option<String>, lines: Vec<String>) {
+        if let Some(tmp_poem) = self.tmp_poem.take() {
+            self.close_tmp_poem(tmp_poem);
+        }
+        self.close_tmp_poem(TmpPoem { nn, lang: Some(lang), title, lines });
+    }
+
+    /// Закрытие раздела: строки относятся к языку, заданному маркером,
+    /// либо к первому по порядку языку, которого у стихотворения ещё нет.
+    /// Если письменность раздела не совпадает с языком, это ошибка; раздел без маркера
+    /// тогда относится к первому недостающему языку с той же письменностью, если он есть
+    fn close_tmp_poem(&mut self, tmp_poem: TmpPoem) {
+        let nn = tmp_poem.nn;
+        if self.book.poems.contains_key(&nn) {
+            // Ошибка, две поэмы с одним номером
+            self.report.add(&Error::DuplicatePoem{number: nn});
+            return;
+        }
+        // Запись для номера создаётся только для принятого раздела: иначе отвергнутый
+        // раздел оставил бы пустое "неполное" стихотворение
+        let parts = self.tmp_poems.get(&nn);
+        let has = |lang: &str| parts.is_some_and(|p| p.contains_key(lang));
+        let detected = script::detect(&tmp_poem.lines);
+        let mismatch = |lang: &str| matches!(
+            (detected, script::language_script(lang)),
+            (Some(found), Some(expected)) if found != expected
+        );
+        let script_name = detected.map(|s| s.name().to_string()).unwrap_or_default();
+        let lang = match tmp_poem.lang {
+            Some(lang) => {
+                if mismatch(&lang) {
+                    self.report.add(&Error::LanguageMismatch{number: nn, lang: lang.clone(), script: script_name});
+                }
+                lang
+            }
+            None => {
+                let missing = self.languages.iter().filter(|l| !has(l)).collect::<Vec<_>>();
+                match missing.first() {
+                    Some(first) if mismatch(first) => {
+                        // Раздел не на своём месте: ошибка, и строки - языку с их письменностью
+                        self.report.add(&Error::LanguageMismatch{number: nn, lang: first.to_string(), script: script_name});
+                        missing.iter().find(|l| script::language_script(l) == detected).unwrap_or(first).to_string()
                     }
+                    Some(first) => first.to_string(),
                     None => {
-                        self.report.add(&Error::NoTranslationForPoem { number: self.book.nn });
-
-                        (Some(self.book), self.report)
-                        // (None, self.report)
+                        self.report.add(&Error::DuplicatePoem{number: nn});
+                        return;
                     }
                 }
             }
-            None => {
-                self.report.add(&Error::NoPoemsInTheBook { number: self.book.nn });
+        };
+        if !self.languages.contains(&lang) {
+            self.report.add(&Error::UnknownLanguage{number: nn, lang});
+            return;
+        }
+        if has(&lang) {
+            self.report.add(&Error::DuplicatePoem{number: nn});
+            return;
+        }
+        if let Some(title) = tmp_poem.title {
+            self.tmp_titles.entry(nn).or_default().insert(lang.clone(), title);
+        }
+        let parts = self.tmp_poems.entry(nn).or_default();
+        parts.insert(lang, tmp_poem.lines);
 
-                (Some(self.book), self.report)
-                // (None, self.report)
-            }
+        // Все языки собраны, добавляем полностью готовое стихотворенье
+        if self.languages.iter().all(|l| parts.contains_key(l)) {
+            let parts = self.tmp_poems.remove(&nn).unwrap_or_default();
+            let poem = self.take_poem(nn, parts);
+            self.book.add(poem);
+        }
+    }
+
+    /// Завершение обработки книги. Финализация модели книги.
+    pub fn build(mut self) -> (Option<Book>, BookReport) {
+        if let Some(tmp_poem) = self.tmp_poem.take() {
+            self.close_tmp_poem(tmp_poem);
+        }
+        self.close_tmp_blocks(None);
+        if self.book.poems.is_empty() && self.tmp_poems.is_empty() {
+            self.report.add(&Error::NoPoemsInTheBook { number: self.book.nn });
         }
+        // Стихотворения, для которых нашлись не все переводы
+        for (nn, parts) in self.tmp_poems.drain().sorted_by_key(|(nn, _)| *nn) {
+            self.report.add(&Error::NoTranslationForPoem { number: nn });
+            let poem = self.take_poem(nn, parts);
+            self.book.add_incomplete(poem);
+        }
+        (Some(self.book), self.report)
+    }
+
+    /// Стихотворение из собранных разделов и названий, в порядке языков
+    fn take_poem(&mut self, nn: u32, mut parts: IndexMap<String, Vec<String>>) -> Poem {
+        let mut titles = self.tmp_titles.remove(&nn).unwrap_or_default();
+        let mut poem = Poem::new(nn, self.languages.iter().filter_map(|l| parts.swap_remove_entry(l)).collect());
+        poem.titles = self.languages.iter().filter_map(|l| titles.swap_remove_entry(l)).collect();
+        poem
     }
 }
 
@@ -155,4 +288,157 @@ mod tests {
         println!(">>>> Error Report: {:#?}", err_report);
         Ok(())
     }
+
+    #[test]
+    fn test_three_languages() {
+        let languages = vec!["en".to_string(), "ru".to_string(), "uk".to_string()];
+        let mut builder = BookBuilder::with_languages(7, languages);
+        builder.proc_number(1);
+        builder.proc_line(String::from("Qwerty 1 1"));
+        builder.proc_number(1);
+        builder.proc_line(String::from("Йцукен 1 1"));
+        builder.proc_number(1);
+        builder.proc_line(String::from("Їжак 1 1"));
+        builder.proc_number(2);
+        builder.proc_line(String::from("Qwerty 2 1"));
+        builder.proc_number(2);
+        builder.proc_lang_line("uk", String::from("Їжак 2 1"));
+        builder.proc_number(2);
+        builder.proc_line(String::from("Йцукен 2 1"));
+
+        let (book, _) = builder.build();
+        let book = book.unwrap();
+        let poems = book.get_ordered_poems();
+        assert_eq!(2, poems.len());
+        assert_eq!(vec!["en", "ru", "uk"], poems[0].languages().collect::<Vec<_>>());
+        assert_eq!("Їжак 1 1", poems[0].lines("uk")[0]);
+        assert_eq!(vec!["en", "ru", "uk"], poems[1].languages().collect::<Vec<_>>());
+        assert_eq!("Йцукен 2 1", poems[1].lines("ru")[0]);
+        assert_eq!("Їжак 2 1", poems[1].lines("uk")[0]);
+    }
+
+    #[test]
+    fn test_incomplete() {
+        let mut builder = BookBuilder::with_languages(7, vec!["en".to_string(), "ru".to_string()]);
+        builder.proc_number(1);
+        builder.proc_line(String::from("Qwerty 1 1"));
+        builder.proc_number(2);
+        builder.proc_line(String::from("Qwerty 2 1"));
+        builder.proc_number(1);
+        builder.proc_line(String::from("Йцукен 1 1"));
+
+        let (book, report) = builder.build();
+        let book = book.unwrap();
+        assert_eq!(1, book.poems.len());
+        assert_eq!("Qwerty 2 1", book.incomplete[&2].lines("en")[0]);
+        assert_eq!(Some((1, 2)), book.range());
+        assert_eq!(50.0, book.completeness());
+        assert_eq!(vec!["NoTranslationForPoem { number: 2 }"], report.errors());
+    }
+
+    #[test]
+    fn test_rejected_section() {
+        let mut builder = BookBuilder::with_languages(7, vec!["en".to_string(), "ru".to_string()]);
+        builder.proc_section(1, "en".to_string(), None, vec!["Qwerty 1 1".to_string()]);
+        builder.proc_section(1, "ru".to_string(), None, vec!["Йцукен 1 1".to_string()]);
+        // Отвергнутый раздел не создаёт неполного стихотворения
+        builder.proc_section(2, "de".to_string(), None, vec!["Qwertz 2 1".to_string()]);
+
+        let (book, report) = builder.build();
+        let book = book.unwrap();
+        assert!(book.incomplete.is_empty());
+        assert_eq!(vec![r#"UnknownLanguage { number: 2, lang: "de" }"#], report.errors());
+    }
+
+    #[test]
+    fn test_language_mismatch() {
+        let mut builder = BookBuilder::with_languages(7, vec!["en".to_string(), "ru".to_string()]);
+        // Русский раздел раньше английского
+        builder.proc_number(1);
+        builder.proc_line(String::from("Йцукен 1 1"));
+        builder.proc_number(1);
+        builder.proc_line(String::from("Qwerty 1 1"));
+        // Английский текст в русском разделе
+        builder.proc_number(2);
+        builder.proc_line(String::from("Qwerty 2 1"));
+        builder.proc_number(2);
+        builder.proc_line(String::from("Qwerty 2 2"));
+        // Явный маркер не меняется
+        builder.proc_number(3);
+        builder.proc_line(String::from("Qwerty 3 1"));
+        builder.proc_number(3);
+        builder.proc_lang_line("ru", String::from("Qwerty 3 2"));
+        builder.proc_number(4);
+        builder.proc_line(String::from("6004"));
+        builder.proc_number(4);
+        builder.proc_line(String::from("Йцукен 4 1"));
+
+        let (book, report) = builder.build();
+        let book = book.unwrap();
+        assert_eq!("Йцукен 1 1", book.poems[&1].lines("ru")[0]);
+        assert_eq!("Qwerty 1 1", book.poems[&1].lines("en")[0]);
+        assert_eq!("Qwerty 2 2", book.poems[&2].lines("ru")[0]);
+        assert_eq!("Qwerty 3 2", book.poems[&3].lines("ru")[0]);
+        assert_eq!(vec![
+            r#"LanguageMismatch { number: 1, lang: "en", script: "cyrillic" }"#,
+            r#"LanguageMismatch { number: 2, lang: "ru", script: "latin" }"#,
+            r#"LanguageMismatch { number: 3, lang: "ru", script: "latin" }"#,
+        ], report.errors());
+        assert_eq!(4, book.poems.len());
+    }
+
+    #[test]
+    fn test_number_text() {
+        let mut builder = BookBuilder::with_languages(7, vec!["en".to_string(), "ru".to_string()]);
+        builder.proc_number_text("00,001. (Dedication)");
+        builder.proc_line(String::from("Qwerty 1 1"));
+        builder.proc_number_text("00,001.");
+        builder.proc_line(String::from("Йцукен 1 1"));
+        // Строки после неверного номера не достаются стихотворению 1
+        builder.proc_number_text("00,00l.");
+        builder.proc_line(String::from("Qwerty 2 1"));
+        builder.proc_number_text("00,003.* (Prayer)");
+        builder.proc_line(String::from("Qwerty 3 1"));
+
+        let (book, report) = builder.build();
+        let book = book.unwrap();
+        assert_eq!(vec!["Qwerty 1 1"], book.poems[&1].lines("en"));
+        assert_eq!("(Dedication)", book.poems[&1].titles["en"]);
+        assert!(!book.poems[&1].titles.contains_key("ru"));
+        assert_eq!("(Prayer)", book.incomplete[&3].titles["en"]);
+        assert_eq!(vec![
+            r#"BadPoemNumber { text: "00,00l." }"#,
+            "NoTranslationForPoem { number: 3 }",
+        ], report.errors());
+    }
+
+    #[test]
+    fn test_blocks() {
+        let mut builder = BookBuilder::with_languages(7, vec!["en".to_string(), "ru".to_string()]);
+        builder.proc_block(BlockKind::FrontMatter, String::from("Seventy-Seven"));
+        builder.proc_block(BlockKind::FrontMatter, String::from("Thousand"));
+        builder.proc_block(BlockKind::SectionHeading, String::from("Part I"));
+        builder.proc_number(1);
+        builder.proc_line(String::from("Qwerty 1 1"));
+        builder.proc_block(BlockKind::Footnote, String::from("* Note"));
+        builder.proc_number(1);
+        builder.proc_line(String::from("Йцукен 1 1"));
+        builder.proc_block(BlockKind::Footnote, String::from("* Примечание"));
+
+        let (book, report) = builder.build();
+        let book = book.unwrap();
+        assert!(report.errors().is_empty());
+        assert_eq!(1, book.poems.len());
+        assert_eq!(4, book.blocks.len());
+        assert_eq!(vec!["Seventy-Seven", "Thousand"], book.blocks[0].lines);
+        assert_eq!((BlockKind::SectionHeading, Some(1)), (book.blocks[1].kind, book.blocks[1].before));
+        // Сноски - за стихотворением, раздел которого они завершают
+        assert_eq!((Some("en".to_string()), None, Some(1)), (book.blocks[2].lang.clone(), book.blocks[2].before, book.blocks[2].after));
+        assert_eq!((Some("ru".to_string()), None, Some(1)), (book.blocks[3].lang.clone(), book.blocks[3].before, book.blocks[3].after));
+
+        let placed = book.blocks_by_poem();
+        assert_eq!(2, placed.before["1"].len());
+        assert_eq!(2, placed.after["1"].len());
+        assert!(placed.end.is_empty());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use tera::{Context, Tera};
+
+use crate::book::{Block, BlockKind, Book};
+use crate::collection::{self, Navigation, Pagination, Volume};
+use crate::config::CONFIG;
+use crate::lint::{Fix, Warning};
+use crate::poem::Poem;
+use crate::report::{self, BookReport};
+use crate::status::{self, Status};
+use crate::{epub, filters, render, search, template};
+use crate::{Error, Result};
+
+lazy_static! {
+    static ref RE_UNDEFINED: Regex =
+        Regex::new(r"Variable `(.+?)` not found in context while rendering '(.+?)'").unwrap();
+}
+
+/// Небольшой том для проверки шаблонов: три полных стихотворения и одно неполное
+pub fn sample_book() -> Book {
+    let lines = |lang: &str, nn: u32| (1..=3).map(|i| format!("{} {} line {}", lang, nn, i)).collect();
+    let mut book = Book::new(7);
+    for nn in 6001..=6003 {
+        book.add(Poem::new(nn, CONFIG.languages.iter().map(|l| (l.clone(), lines(l, nn))).collect()));
+    }
+    book.add_incomplete(Poem::new(6004, CONFIG.languages.iter().take(1).map(|l| (l.clone(), lines(l, 6004))).collect()));
+    if let (Some(poem), Some(lang)) = (book.poems.get_mut(&6002), CONFIG.languages.first()) {
+        poem.titles.insert(lang.clone(), "(Title)".to_string());
+    }
+    let lang = CONFIG.languages.first().cloned();
+    book.blocks = vec![
+        Block { kind: BlockKind::FrontMatter, lang: lang.clone(), before: Some(6001), after: None, lines: vec!["Front matter".to_string()] },
+        Block { kind: BlockKind::SectionHeading, lang: lang.clone(), before: Some(6002), after: None, lines: vec!["Section".to_string()] },
+        Block { kind: BlockKind::Footnote, lang, before: None, after: Some(6002), lines: vec!["Footnote".to_string()] },
+    ];
+    book
+}
+
+/// Переходы первой из двух страниц тома, чтобы проверить и разбиение на страницы
+fn sample_navigation(volume: &Volume) -> Navigation {
+    let numbers = volume.book.numbers().into_iter().collect::<Vec<_>>();
+    let (first, second) = numbers.split_at(numbers.len() / 2);
+    let pages = [("Vol. 07.html".to_string(), first.to_vec()), ("Vol. 07-2.html".to_string(), second.to_vec())];
+    Navigation { page: Some(Pagination::new(&pages, 0)), ..Default::default() }
+}
+
+/// Отчёт с ошибками для проверки `problem_template`
+pub fn sample_report() -> BookReport {
+    let mut report = BookReport::new(7);
+    report.add(&Error::DuplicatePoem { number: 6002 });
+    report.add(&Error::NoTranslationForPoem { number: 6004 });
+    report.add_fix(&Fix { rule: "RU001", nn: 6002, lang: "ru".to_string(), line: 1, before: "\"Да\"".to_string(), after: "«Да»".to_string() });
+    report.add_unknown_class("_7_poem-first");
+    report.add_warning(&Warning { rule: "RU003", nn: 6001, lang: "ru".to_string(), line: 2, message: "двойной пробел".to_string() });
+    report
+}
+
+/// Команда `check-templates`: все настроенные шаблоны на примере тома и отчёта
+pub fn run() -> Result<()> {
+    let tera = match template::load_templates() {
+        Ok(tera) => tera,
+        Err(e) => {
+            println!("error    {}: {}", CONFIG.template_pattern, describe(&e));
+            return Err(Error::TemplateCheck { problems: 1 });
+        }
+    };
+    let book = sample_book();
+    let volumes = [Volume::new("Vol. 07.html", sample_book())];
+    filters::set_corpus(&volumes);
+
+    // Шаблон -> (параметр конфигурации, контекст); первый по порядку выигрывает
+    let mut checks: BTreeMap<String, (String, Context)> = BTreeMap::new();
+    let book_context = render::book_context(&book, &sample_navigation(&volumes[0]));
+    for output in CONFIG.outputs().iter().filter(|o| o.renderer == "html") {
+        let name = output.template.clone().unwrap_or_else(|| CONFIG.poem_template.clone());
+        checks.entry(name).or_insert_with(|| (format!("outputs.{}", output.name), book_context.clone()));
+    }
+    checks.entry(CONFIG.poem_template.clone()).or_insert_with(|| ("poem_template".to_string(), book_context.clone()));
+    checks.entry(CONFIG.problem_template.clone())
+        .or_insert_with(|| ("problem_template".to_string(), report::report_context(&sample_report())));
+    if !CONFIG.index_template.is_empty() {
+        checks.entry(CONFIG.index_template.clone())
+            .or_insert_with(|| ("index_template".to_string(), collection::index_context(&volumes).unwrap_or_default()));
+    }
+    if !CONFIG.lookup_template.is_empty() {
+        checks.entry(CONFIG.lookup_template.clone())
+            .or_insert_with(|| ("lookup_template".to_string(), collection::lookup_context(&volumes).unwrap_or_default()));
+    }
+    if !CONFIG.search_template.is_empty() {
+        checks.entry(CONFIG.search_template.clone())
+            .or_insert_with(|| ("search_template".to_string(), search::page_context()));
+    }
+    if !CONFIG.status_template.is_empty() {
+        checks.entry(CONFIG.status_template.clone())
+            .or_insert_with(|| ("status_template".to_string(), Status::new(&volumes).map(|s| status::status_context(&s)).unwrap_or_default()));
+    }
+    if CONFIG.epub.enabled || CONFIG.epub.collection {
+        for name in epub::TEMPLATE_NAMES {
+            checks.entry(name.to_string()).or_insert_with(|| ("epub".to_string(), epub::book_context(&book)));
+        }
+    }
+
+    let mut problems = 0;
+    for (name, (source, context)) in &checks {
+        match check_template(&tera, name, context) {
+            None if tera.templates[name].path.is_none() => println!("ok       {} (built-in)", name),
+            None => println!("ok       {}", name),
+            Some(problem) => {
+                problems += 1;
+                println!("error    {} ({}): {}", name, source, problem);
+            }
+        }
+    }
+    if problems > 0 {
+        return Err(Error::TemplateCheck { problems });
+    }
+    Ok(())
+}
+
+/// Описание проблемы шаблона `name` с контекстом `context`, если она есть
+pub fn check_template(tera: &Tera, name: &str, context: &Context) -> Option<String> {
+    if !tera.templates.contains_key(name) {
+        return Some("template not found".to_string());
+    }
+    let e = tera.render(name, context).err()?;
+    let text = describe(&e);
+    match RE_UNDEFINED.captures(&text) {
+        Some(caps) => {
+            let (variable, rendering) = (&caps[1], &caps[2]);
+            Some(match locate(tera, rendering, variable) {
+                Some((path, line)) => format!("{}:{}: variable `{}` is not defined", path, line, variable),
+                None => format!("variable `{}` is not defined", variable),
+            })
+        }
+        None => Some(text),
+    }
+}
+
+/// Все сообщения цепочки ошибок Tera
+fn describe(e: &tera::Error) -> String {
+    let mut res = e.to_string();
+    let mut source = std::error::Error::source(e);
+    while let Some(e) = source {
+        res.push_str(": ");
+        res.push_str(&e.to_string());
+        source = e.source();
+    }
+    res
+}
+
+/// Файл и номер строки, где используется `variable`: сначала в самом шаблоне,
+/// затем в родительских, затем в любом (для `include`)
+fn locate(tera: &Tera, name: &str, variable: &str) -> Option<(String, usize)> {
+    let mut names = vec![name.to_string()];
+    if let Some(t) = tera.templates.get(name) {
+        names.extend(t.parents.iter().cloned());
+    }
+    let mut others = tera.templates.keys().filter(|n| !names.contains(n)).cloned().collect::<Vec<_>>();
+    others.sort();
+    names.extend(others);
+
+    names.iter()
+        .filter_map(|n| tera.templates.get(n)?.path.clone())
+        .find_map(|path| {
+            let text = fs::read_to_string(&path).ok()?;
+            let line = text.lines().position(|l| l.contains(variable))?;
+            Some((path, line + 1))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_template() -> Result<()> {
+        let dir = std::env::temp_dir().join("html-77000-check-test");
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("base.html"), "<h1>{% block title %}{% endblock title %}</h1>\n{{ footer }}\n")?;
+        fs::write(dir.join("page.html"), "{% extends \"base.html\" %}\n{% block title %}\n{{ book.nn }} {{ book.title }}\n{% endblock title %}\n")?;
+        let tera = Tera::new(&format!("{}/*.html", dir.display()))?;
+
+        let mut context = Context::new();
+        context.insert("book", &sample_book());
+        let problem = check_template(&tera, "page.html", &context).unwrap();
+        assert!(problem.ends_with("page.html:3: variable `book.title` is not defined"), "{}", problem);
+
+        assert_eq!(Some("template not found".to_string()), check_template(&tera, "absent.html", &context));
+
+        fs::write(dir.join("page.html"), "{% extends \"base.html\" %}\n{% block title %}{{ book.nn }}{% endblock title %}\n")?;
+        let tera = Tera::new(&format!("{}/*.html", dir.display()))?;
+        let problem = check_template(&tera, "page.html", &context).unwrap();
+        assert!(problem.ends_with("base.html:2: variable `footer` is not defined"), "{}", problem);
+
+        context.insert("footer", "");
+        assert_eq!(None, check_template(&tera, "page.html", &context));
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_shipped_templates() {
+        let tera = template::load_templates().unwrap();
+        let book = sample_book();
+        let volumes = [Volume::new("Vol. 07.html", sample_book())];
+        assert_eq!(None, check_template(&tera, CONFIG.poem_template.as_str(), &render::book_context(&book, &Navigation::default())));
+        let context = render::book_context(&book, &sample_navigation(&volumes[0]));
+        assert_eq!(None, check_template(&tera, CONFIG.poem_template.as_str(), &context));
+        assert_eq!(None, check_template(&tera, CONFIG.index_template.as_str(), &collection::index_context(&volumes).unwrap()));
+        assert_eq!(None, check_template(&tera, CONFIG.lookup_template.as_str(), &collection::lookup_context(&volumes).unwrap()));
+        assert_eq!(None, check_template(&tera, CONFIG.search_template.as_str(), &search::page_context()));
+        assert_eq!(None, check_template(&tera, CONFIG.problem_template.as_str(), &report::report_context(&sample_report())));
+        for name in epub::TEMPLATE_NAMES {
+            assert_eq!(None, check_template(&tera, name, &epub::book_context(&book)));
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{Error, Result};
+
+pub static USAGE: &str = "\
+Usage:
+    html-77000 [CONFIG]
+    html-77000 [--config CONFIG] build [--fix]
+    html-77000 [--config CONFIG] search [--lang LANG] [--phrase | --regex] [--cached] QUERY...
+    html-77000 [--config CONFIG] status [--json]
+    html-77000 [--config CONFIG] check-templates
+    html-77000 [--config CONFIG] init [--force]
+    html-77000 [--config CONFIG] diff OLD NEW
+
+Commands:
+    build       Parse every volume from src_dir and write all outputs to res_dir (default);
+                --fix applies safe typographic corrections and lists them in the reports
+    search      Print poems matching QUERY, with all their languages
+    status      Print translation progress per volume; --json prints the same data as
+                <res_dir>/status.json
+    check-templates
+                Render every configured template against a sample volume and report problems
+    init        Write the built-in templates to the template_pattern directory for editing;
+                existing files are kept unless --force is given
+    diff        Compare two versions of a volume (HTML or JSON) poem by poem
+
+Search options:
+    --lang LANG     Search only the text in LANG (may be repeated)
+    --phrase        Match QUERY as a phrase instead of separate words
+    --regex         Match QUERY as a regular expression against each line
+    --cached        Read volumes from <res_dir>/all_volumes.jsonl instead of parsing src_dir
+";
+
+static COMMANDS: [&str; 6] = ["build", "search", "status", "check-templates", "init", "diff"];
+
+/// Разобранная командная строка
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Build { fix: bool },
+    Search(SearchArgs),
+    Status { json: bool },
+    CheckTemplates,
+    Init { force: bool },
+    Diff { old: String, new: String },
+}
+
+#[derive(Debug, PartialEq, Default)]
+pub struct SearchArgs {
+    pub query: String,
+    pub langs: Vec<String>,
+    pub mode: SearchMode,
+    pub cached: bool,
+}
+
+#[derive(Debug, PartialEq, Default, Clone, Copy)]
+pub enum SearchMode {
+    /// Все слова запроса, в любом месте стихотворения
+    #[default]
+    Words,
+    Phrase,
+    Regex,
+}
+
+/// Имя файла конфигурации: `--config FILE` или единственный аргумент, если это не команда
+pub fn config_name(args: &[String]) -> Option<&str> {
+    if let Some(i) = args.iter().position(|a| a == "--config") {
+        return args.get(i + 1).map(String::as_str);
+    }
+    match args {
+        [_, name] if !COMMANDS.contains(&name.as_str()) && !name.starts_with('-') => Some(name),
+        _ => None,
+    }
+}
+
+/// Команда из аргументов (первый - имя программы)
+pub fn parse_command(args: &[String]) -> Result<Command> {
+    let mut rest = Vec::new();
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        if arg == "--config" {
+            iter.next();
+        } else {
+            rest.push(arg.as_str());
+        }
+    }
+    match rest.as_slice() {
+        [] | ["build"] => Ok(Command::Build { fix: false }),
+        ["build", "--fix"] => Ok(Command::Build { fix: true }),
+        // Старый вызов: единственный аргумент - файл конфигурации
+        [name] if !COMMANDS.contains(name) && !name.starts_with('-') => Ok(Command::Build { fix: false }),
+        ["search", args @ ..] => parse_search(args).map(Command::Search),
+        ["status"] => Ok(Command::Status { json: false }),
+        ["status", "--json"] => Ok(Command::Status { json: true }),
+        ["check-templates"] => Ok(Command::CheckTemplates),
+        ["init"] => Ok(Command::Init { force: false }),
+        ["init", "--force"] => Ok(Command::Init { force: true }),
+        ["diff", old, new] => Ok(Command::Diff { old: old.to_string(), new: new.to_string() }),
+        _ => Err(Error::Usage(USAGE.to_string())),
+    }
+}
+
+fn parse_search(args: &[&str]) -> Result<SearchArgs> {
+    let mut res = SearchArgs::default();
+    let mut query = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match *arg {
+            "--lang" => res.langs.push(iter.next().ok_or_else(|| Error::Usage(USAGE.to_string()))?.to_string()),
+            "--phrase" => res.mode = SearchMode::Phrase,
+            "--regex" => res.mode = SearchMode::Regex,
+            "--cached" => res.cached = true,
+            arg if arg.starts_with("--") => return Err(Error::Usage(USAGE.to_string())),
+            arg => query.push(arg),
+        }
+    }
+    if query.is_empty() {
+        return Err(Error::Usage(USAGE.to_string()));
+    }
+    res.query = query.join(" ");
+    // Запрос без слов или с ошибкой в выражении - до чтения томов
+    crate::search::Query::new(&res.query, res.mode)?;
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_config_name() {
+        assert_eq!(None, config_name(&args("html")));
+        assert_eq!(Some("my.toml"), config_name(&args("html my.toml")));
+        assert_eq!(None, config_name(&args("html search")));
+        assert_eq!(Some("my.toml"), config_name(&args("html --config my.toml search tree")));
+    }
+
+    #[test]
+    fn test_parse_command() -> Result<()> {
+        assert_eq!(Command::Build { fix: false }, parse_command(&args("html"))?);
+        assert_eq!(Command::Build { fix: false }, parse_command(&args("html my.toml"))?);
+        assert_eq!(Command::Build { fix: false }, parse_command(&args("html --config my.toml build"))?);
+        assert_eq!(Command::Build { fix: true }, parse_command(&args("html build --fix"))?);
+        assert_eq!(
+            Command::Search(SearchArgs {
+                query: "tree of service".to_string(),
+                langs: vec!["en".to_string()],
+                mode: SearchMode::Phrase,
+                cached: true,
+            }),
+            parse_command(&args("html search --lang en --phrase --cached tree of service"))?
+        );
+        assert_eq!(Command::Status { json: true }, parse_command(&args("html status --json"))?);
+        assert_eq!(Command::CheckTemplates, parse_command(&args("html check-templates"))?);
+        assert_eq!(Command::Init { force: true }, parse_command(&args("html --config c.toml init --force"))?);
+        assert!(parse_command(&args("html init templates")).is_err());
+        assert_eq!(
+            Command::Diff { old: "old/07.html".to_string(), new: "07.json".to_string() },
+            parse_command(&args("html diff old/07.html 07.json"))?
+        );
+        assert!(parse_command(&args("html diff 07.html")).is_err());
+        assert!(parse_command(&args("html search")).is_err());
+        assert!(parse_command(&args("html search --bogus x")).is_err());
+        assert!(matches!(parse_command(&args("html search —")), Err(Error::Usage(_))));
+        assert!(parse_command(&args("html frobnicate now")).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Serialize;
+use tera::Context;
+
+use crate::book::Book;
+use crate::config::{Output, CONFIG};
+use crate::locale;
+use crate::render::output_renderer;
+use crate::template::TEMPLATES;
+use crate::utils::{format_file_name, page_file_name, relative_link};
+use crate::Result;
+
+/// Том, прочитанный в этом запуске, вместе с именем его исходного файла
+#[derive(Debug)]
+pub struct Volume {
+    /// Имя исходного файла: "Vol. 07.html"
+    pub src_file_name: String,
+    pub book: Book,
+    /// Ошибок в отчёте о разборе
+    pub problems: usize,
+}
+
+impl Volume {
+    pub fn new(src_file_name: &str, book: Book) -> Self {
+        Self { src_file_name: src_file_name.to_string(), book, problems: 0 }
+    }
+
+    /// "Vol. 07.html" -> "Vol. 07"
+    pub fn src_stem(&self) -> &str {
+        Path::new(&self.src_file_name).file_stem().and_then(|s| s.to_str()).unwrap_or(&self.src_file_name)
+    }
+
+    /// Путь к тому относительно `res_dir` для выхода `output`
+    pub fn link_for(&self, output: &Output) -> Result<String> {
+        let ext = output_renderer(output)?.extension().to_string();
+        format_file_name(&output.file_name, self.book.nn, self.src_stem(), &ext)
+    }
+
+    /// Путь к тому относительно `res_dir` для основного выхода
+    pub fn link(&self) -> Result<String> {
+        match CONFIG.primary_output() {
+            Some(output) => self.link_for(&output),
+            None => Ok(String::new()),
+        }
+    }
+
+    /// Страницы тома в выходе `output`: путь относительно `res_dir` и номера стихотворений
+    pub fn pages(&self, output: &Output) -> Result<Vec<(String, Vec<u32>)>> {
+        let link = self.link_for(output)?;
+        let numbers = self.book.get_ordered_poems().iter().map(|p| p.nn).collect::<Vec<_>>();
+        if output.page_size == 0 || numbers.len() <= output.page_size {
+            return Ok(vec![(link, numbers)]);
+        }
+        Ok(numbers.chunks(output.page_size)
+            .enumerate()
+            .map(|(i, chunk)| (page_file_name(&link, i + 1), chunk.to_vec()))
+            .collect())
+    }
+
+    /// Страница тома в основном выходе для каждого стихотворения
+    pub fn poem_links(&self) -> Result<BTreeMap<u32, String>> {
+        let pages = match CONFIG.primary_output() {
+            Some(output) => self.pages(&output)?,
+            None => return Ok(BTreeMap::new()),
+        };
+        Ok(pages.into_iter()
+            .flat_map(|(link, numbers)| numbers.into_iter().map(move |nn| (nn, link.clone())))
+            .collect())
+    }
+
+    /// Том из стихотворений `numbers` - одна страница, с блоками при них;
+    /// блоки конца тома - на странице с последним стихотворением
+    pub fn page_book(&self, numbers: &[u32]) -> Book {
+        let mut res = Book::new(self.book.nn);
+        for nn in numbers {
+            if let Some(poem) = self.book.poems.get(nn) {
+                res.add(poem.clone());
+            } else if let Some(poem) = self.book.incomplete.get(nn) {
+                res.add_incomplete(poem.clone());
+            }
+        }
+        let last_page = self.book.range().is_some_and(|(_, last)| numbers.contains(&last));
+        let all = self.book.numbers();
+        res.blocks = self.book.blocks.iter()
+            .filter(|b| Book::place(b, &all).map_or(last_page, |(nn, _)| numbers.contains(&nn)))
+            .cloned()
+            .collect();
+        res
+    }
+}
+
+/// Ссылка на другой том
+#[derive(Serialize, Debug)]
+pub struct VolumeLink {
+    pub nn: u32,
+    /// Относительно файла текущего тома
+    pub link: String,
+}
+
+/// Соседние тома для перехода из текущего
+#[derive(Serialize, Debug, Default)]
+pub struct Navigation {
+    pub prev: Option<VolumeLink>,
+    pub next: Option<VolumeLink>,
+    /// Текущая страница, если том разбит на страницы
+    pub page: Option<Pagination>,
+}
+
+impl Navigation {
+    /// Соседи `volumes[i]` (тома упорядочены по номеру) в том же выходе
+    pub fn new(volumes: &[Volume], i: usize, output: &Output) -> Result<Self> {
+        let from = volumes[i].link_for(output)?;
+        let link = |v: &Volume| -> Result<VolumeLink> {
+            Ok(VolumeLink { nn: v.book.nn, link: relative_link(&from, &v.link_for(output)?) })
+        };
+        Ok(Self {
+            prev: i.checked_sub(1).map(|p| link(&volumes[p])).transpose()?,
+            next: volumes.get(i + 1).map(link).transpose()?,
+            page: None,
+        })
+    }
+}
+
+/// Ссылка на страницу тома
+#[derive(Serialize, Debug, Clone)]
+pub struct PageLink {
+    /// Номер страницы, с 1
+    pub nn: usize,
+    pub first: u32,
+    pub last: u32,
+    /// Относительно файла текущей страницы
+    pub link: String,
+}
+
+/// Положение страницы среди страниц тома, `page` в шаблоне
+#[derive(Serialize, Debug)]
+pub struct Pagination {
+    pub nn: usize,
+    pub count: usize,
+    pub first: u32,
+    pub last: u32,
+    pub prev: Option<PageLink>,
+    pub next: Option<PageLink>,
+    pub pages: Vec<PageLink>,
+    /// Номер стихотворения -> страница со ссылкой на него
+    pub poems: BTreeMap<u32, String>,
+    /// Только на первой странице: стихотворения других страниц -> ссылка на них,
+    /// чтобы старые ссылки "Vol. 07.html#6051" вели дальше без скриптов
+    pub anchors: BTreeMap<u32, String>,
+}
+
+impl Pagination {
+    /// Страница `i` из `Volume::pages`
+    pub fn new(pages: &[(String, Vec<u32>)], i: usize) -> Self {
+        let from = &pages[i].0;
+        let links = pages.iter()
+            .enumerate()
+            .map(|(n, (link, numbers))| PageLink {
+                nn: n + 1,
+                first: numbers.first().copied().unwrap_or_default(),
+                last: numbers.last().copied().unwrap_or_default(),
+                link: relative_link(from, link),
+            })
+            .collect::<Vec<_>>();
+        let poems: BTreeMap<u32, String> = links.iter()
+            .zip(pages)
+            .flat_map(|(link, (_, numbers))| numbers.iter().map(|nn| (*nn, link.link.clone())))
+            .collect();
+        let anchors = match i {
+            0 => poems.iter()
+                .filter(|(nn, _)| !pages[0].1.contains(nn))
+                .map(|(nn, link)| (*nn, link.clone()))
+                .collect(),
+            _ => BTreeMap::new(),
+        };
+        Self {
+            nn: i + 1,
+            count: pages.len(),
+            first: links[i].first,
+            last: links[i].last,
+            prev: i.checked_sub(1).map(|p| links[p].clone()),
+            next: links.get(i + 1).cloned(),
+            pages: links,
+            poems,
+            anchors,
+        }
+    }
+}
+
+/// Строка оглавления для одного тома
+#[derive(Serialize, Debug)]
+pub struct VolumeSummary {
+    pub nn: u32,
+    pub first: Option<u32>,
+    pub last: Option<u32>,
+    /// Полностью переведённые стихотворения
+    pub count: usize,
+    pub incomplete: usize,
+    pub completeness: f64,
+    pub link: String,
+}
+
+impl VolumeSummary {
+    pub fn new(volume: &Volume) -> Result<Self> {
+        let book = &volume.book;
+        let range = book.range();
+        Ok(Self {
+            nn: book.nn,
+            first: range.map(|r| r.0),
+            last: range.map(|r| r.1),
+            count: book.poems.len(),
+            incomplete: book.incomplete.len(),
+            completeness: book.completeness(),
+            link: volume.link()?,
+        })
+    }
+}
+
+/// Номер стихотворения для страницы поиска по номеру
+#[derive(Serialize, Debug)]
+struct PoemLink<'a> {
+    nn: u32,
+    nn_str: &'a str,
+    /// Страница тома с этим стихотворением
+    link: String,
+}
+
+/// Том и номера его стихотворений
+#[derive(Serialize, Debug)]
+struct VolumeNumbers<'a> {
+    nn: u32,
+    link: String,
+    poems: Vec<PoemLink<'a>>,
+}
+
+/// Страница поиска по номеру: `lookup_template` -> "<res_dir>/numbers.html",
+/// каждая ссылка ведёт на "том.html#номер"
+pub fn write_lookup(res_dir: &Path, volumes: &[Volume]) -> Result<()> {
+    let text = TEMPLATES.render(CONFIG.lookup_template.as_str(), &lookup_context(volumes)?)?;
+    std::fs::write(res_dir.join("numbers.html"), text)?;
+    Ok(())
+}
+
+/// Контекст `lookup_template`
+pub fn lookup_context(volumes: &[Volume]) -> Result<Context> {
+    let mut numbers = volumes.iter()
+        .map(|v| -> Result<VolumeNumbers> {
+            let mut links = v.poem_links()?;
+            Ok(VolumeNumbers {
+                nn: v.book.nn,
+                link: v.link()?,
+                poems: v.book.get_ordered_poems().into_iter()
+                    .map(|p| PoemLink { nn: p.nn, nn_str: &p.nn_str, link: links.remove(&p.nn).unwrap_or_default() })
+                    .collect(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    numbers.sort_by_key(|v| v.nn);
+
+    let mut context = locale::context();
+    context.insert("volumes", &numbers);
+    context.insert("languages", &CONFIG.languages);
+    Ok(context)
+}
+
+/// Сводка по томам в порядке номеров
+pub fn summarize(volumes: &[Volume]) -> Result<Vec<VolumeSummary>> {
+    let mut res = volumes.iter().map(VolumeSummary::new).collect::<Result<Vec<_>>>()?;
+    res.sort_by_key(|s| s.nn);
+    Ok(res)
+}
+
+/// Оглавление всего собрания: `index_template` -> "<res_dir>/index.html"
+pub fn write_index(res_dir: &Path, volumes: &[Volume]) -> Result<()> {
+    let text = TEMPLATES.render(CONFIG.index_template.as_str(), &index_context(volumes)?)?;
+    std::fs::write(res_dir.join("index.html"), text)?;
+    Ok(())
+}
+
+/// Контекст `index_template`
+pub fn index_context(volumes: &[Volume]) -> Result<Context> {
+    let summaries = summarize(volumes)?;
+    let count = summaries.iter().map(|s| s.count).sum::<usize>();
+    let total = count + summaries.iter().map(|s| s.incomplete).sum::<usize>();
+
+    let mut context = locale::context();
+    context.insert("volumes", &summaries);
+    context.insert("languages", &CONFIG.languages);
+    context.insert("count", &count);
+    context.insert("total", &total);
+    context.insert("completeness", &if total == 0 { 0.0 } else { count as f64 * 100.0 / total as f64 });
+    Ok(context)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::book::tests::get_test_book;
+    use crate::book::{Block, BlockKind};
+
+    use super::*;
+
+    #[test]
+    fn test_summarize() -> Result<()> {
+        let volumes = vec![Volume::new("Vol. 07.html", get_test_book())];
+        let summaries = summarize(&volumes)?;
+        assert_eq!(1, summaries.len());
+        assert_eq!(Some(1), summaries[0].first);
+        assert_eq!(Some(7), summaries[0].last);
+        assert_eq!(7, summaries[0].count);
+        assert_eq!(100.0, summaries[0].completeness);
+        assert_eq!("Vol. 07.html", summaries[0].link);
+        Ok(())
+    }
+
+    #[test]
+    fn test_index() -> Result<()> {
+        let context = index_context(&[Volume::new("Vol. 07.html", get_test_book())])?;
+        let text = TEMPLATES.render(CONFIG.index_template.as_str(), &context)?;
+        assert!(text.contains(r#"<a href="Vol.%2007.html">"#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_navigation() -> Result<()> {
+        let volumes = [6, 7, 8].map(|n| {
+            let mut book = get_test_book();
+            book.nn = n;
+            Volume::new(&format!("Vol. {:02}.html", n), book)
+        });
+        let output: Output = toml::from_str("name = 'web'\nfile_name = 'web/{volume:02}.html'").unwrap();
+
+        let nav = Navigation::new(&volumes, 0, &output)?;
+        assert!(nav.prev.is_none());
+        assert_eq!("07.html", nav.next.unwrap().link);
+
+        let nav = Navigation::new(&volumes, 1, &output)?;
+        assert_eq!(6, nav.prev.unwrap().nn);
+        assert_eq!(8, nav.next.unwrap().nn);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pages() -> Result<()> {
+        let volume = Volume::new("Vol. 07.html", get_test_book());
+        let mut output: Output = toml::from_str("name = 'web'\nfile_name = 'web/{volume:02}.html'").unwrap();
+        assert_eq!(vec![("web/07.html".to_string(), (1..=7).collect::<Vec<_>>())], volume.pages(&output)?);
+
+        output.page_size = 3;
+        let pages = volume.pages(&output)?;
+        assert_eq!(vec!["web/07.html", "web/07-2.html", "web/07-3.html"], pages.iter().map(|p| &p.0).collect::<Vec<_>>());
+        assert_eq!(vec![7], pages[2].1);
+        assert_eq!(vec![4, 5, 6], volume.page_book(&pages[1].1).poems.keys().copied().collect::<Vec<_>>());
+
+        // Неполные стихотворения тоже попадают на страницы
+        let mut book = get_test_book();
+        let poem = book.poems.remove(&5).unwrap();
+        book.add_incomplete(poem);
+        let volume = Volume::new("Vol. 07.html", book);
+        let pages = volume.pages(&output)?;
+        assert_eq!(vec![4, 5, 6], pages[1].1);
+        assert!(volume.page_book(&pages[1].1).incomplete.contains_key(&5));
+
+        let page = Pagination::new(&pages, 1);
+        assert_eq!((2, 3, 4, 6), (page.nn, page.count, page.first, page.last));
+        assert_eq!("07.html", page.prev.unwrap().link);
+        assert_eq!("07-3.html", page.next.unwrap().link);
+        assert_eq!("07.html", page.poems[&1]);
+        assert_eq!("07-3.html", page.poems[&7]);
+
+        // Блок перед номером, который не стал стихотворением, - на странице следующего
+        let mut book = get_test_book();
+        book.poems.remove(&3);
+        book.blocks.push(Block { kind: BlockKind::SectionHeading, lang: None, before: Some(3), after: None, lines: vec!["Part II".to_string()] });
+        let volume = Volume::new("Vol. 07.html", book);
+        let pages = volume.pages(&output)?;
+        assert_eq!(vec![1, 2, 4], pages[0].1);
+        assert!(volume.page_book(&pages[1].1).blocks.is_empty());
+        let page_book = volume.page_book(&pages[0].1);
+        assert_eq!(1, page_book.blocks.len());
+        assert_eq!(1, page_book.blocks_by_poem().before["4"].len());
+        Ok(())
+    }
+}
@@ -1,10 +1,12 @@
+use std::collections::HashMap;
 use std::env::args;
 use std::fs::read_to_string;
 
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
-use crate::{Result, Error};
-use tracing::{info, warn, error};
+use crate::book::BlockKind;
+use crate::Result;
+use tracing::{info, warn};
 
 static DEFAULT_CONFIG_NAME: &str = "config.toml";
 
@@ -18,19 +20,185 @@ pub struct Config {
     pub template_pattern: String,
     pub poem_template: String,
     pub problem_template: String,
+    /// Страница со списком всех томов; пустая строка - не создавать
+    #[serde(default = "default_index_template")]
+    pub index_template: String,
+    /// Страница "номер стихотворения -> том#номер"; пустая строка - не создавать
+    #[serde(default = "default_lookup_template")]
+    pub lookup_template: String,
+    /// Страница поиска по всему собранию; пустая строка - не создавать
+    #[serde(default = "default_search_template")]
+    pub search_template: String,
+    /// Страница хода перевода по томам (и "status.json"); пустая строка - не создавать
+    #[serde(default = "default_status_template")]
+    pub status_template: String,
+    /// Локаль строк шаблонов: "<locale_dir>/<locale>.toml" поверх встроенной
+    #[serde(default = "default_locale")]
+    pub locale: String,
+    #[serde(default = "default_locale_dir")]
+    pub locale_dir: String,
     pub src_dir: String,
     pub res_dir: String,
+    /// Языки в порядке следования разделов с одинаковым номером стихотворения
+    #[serde(default = "default_languages")]
+    pub languages: Vec<String>,
+    /// Класс абзаца -> код языка, для разделов, размеченных явно
+    #[serde(default)]
+    pub language_classes: HashMap<String, String>,
+    /// Класс абзаца -> вид текста вне стихотворений:
+    /// "front_matter", "section_heading", "footnote"; остальные классы пропускаются
+    #[serde(default)]
+    pub block_classes: HashMap<String, BlockKind>,
+    /// Пропущенные абзацы неизвестных классов - ошибки тома, а не только строки отчёта
+    #[serde(default)]
+    pub unknown_class_errors: bool,
+    /// Код языка -> письменность ("latin", "cyrillic") для проверки разделов;
+    /// для распространённых кодов задана по умолчанию
+    #[serde(default)]
+    pub language_scripts: HashMap<String, String>,
+    /// Теги форматирования, сохраняемые в строках стихотворений (например, "i", "b");
+    /// выводятся только через фильтр `rich_text`, остальная разметка отбрасывается
+    #[serde(default)]
+    pub rich_text_tags: Vec<String>,
+    /// Выходные форматы томов: "html", "text", "markdown"
+    #[serde(default = "default_formats")]
+    pub formats: Vec<String>,
+    /// Именованные выходы; если не заданы, строятся из `formats` и `poem_template`
+    #[serde(default)]
+    pub outputs: Vec<Output>,
+    /// Дополнительно выгружать модель книг в JSON (см. docs/json_export.md)
+    #[serde(default)]
+    pub export_json: bool,
+    #[serde(default)]
+    pub epub: EpubConfig,
+    #[serde(default)]
+    pub lint: LintConfig,
+}
+
+/// Проверка типографики, см. `lint::RULES`
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct LintConfig {
+    pub enabled: bool,
+    /// Коды отключённых правил: ["RU003"]
+    pub disabled: Vec<String>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self { enabled: true, disabled: Vec::new() }
+    }
+}
+
+/// Параметры выгрузки в EPUB 3
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct EpubConfig {
+    /// EPUB для каждого тома
+    pub enabled: bool,
+    /// Дополнительно один EPUB со всеми томами
+    pub collection: bool,
+    /// Пусто - `title` из сообщений локали
+    pub title: String,
+    /// Пусто - `author` из сообщений локали
+    pub author: String,
+    pub publisher: String,
+    /// Свой файл стилей вместо шаблона epub/style.css
+    pub stylesheet: Option<String>,
+}
+
+fn default_languages() -> Vec<String> {
+    vec!["en".to_string(), "ru".to_string()]
+}
+
+fn default_index_template() -> String {
+    "index.html".to_string()
+}
+
+fn default_lookup_template() -> String {
+    "numbers.html".to_string()
+}
+
+fn default_search_template() -> String {
+    "search.html".to_string()
+}
+
+fn default_status_template() -> String {
+    "status.html".to_string()
+}
+
+fn default_locale() -> String {
+    "ru".to_string()
+}
+
+fn default_locale_dir() -> String {
+    "locales".to_string()
+}
+
+fn default_formats() -> Vec<String> {
+    vec!["html".to_string()]
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Один выходной файл на том
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Output {
+    pub name: String,
+    /// Формат, см. `render::renderer`
+    #[serde(default = "default_renderer")]
+    pub renderer: String,
+    /// Шаблон для формата "html"; по умолчанию `poem_template`
+    pub template: Option<String>,
+    /// Шаблон имени файла относительно `res_dir`: `{volume}`, `{volume:02}`, `{source}`, `{ext}`
+    pub file_name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Стихотворений на странице; 0 - весь том одной страницей.
+    /// Страница 1 - `file_name`, остальные - с суффиксом: "Vol. 07-2.html"
+    #[serde(default)]
+    pub page_size: usize,
+}
+
+fn default_renderer() -> String {
+    "html".to_string()
+}
+
+impl Output {
+    /// Выход для формата из `formats`: "Vol. 07.html" -> "Vol. 07.<ext>"
+    fn from_format(format: &str) -> Self {
+        Self {
+            name: format.to_string(),
+            renderer: format.to_string(),
+            template: None,
+            file_name: "{source}.{ext}".to_string(),
+            enabled: true,
+            page_size: 0,
+        }
+    }
 }
 
 impl Config {
+    /// Включённые выходы этого запуска
+    pub fn outputs(&self) -> Vec<Output> {
+        if self.outputs.is_empty() {
+            self.formats.iter().map(|f| Output::from_format(f)).collect()
+        } else {
+            self.outputs.iter().filter(|o| o.enabled).cloned().collect()
+        }
+    }
+
+    /// Выход, на который ссылаются общие страницы: первый HTML, иначе первый включённый
+    pub fn primary_output(&self) -> Option<Output> {
+        let outputs = self.outputs();
+        outputs.iter().find(|o| o.renderer == "html").or(outputs.first()).cloned()
+    }
+
     fn load_config() -> Config {
         let args = args().collect::<Vec<String>>();
-        let config_name = match args.len() {
-            2 => {
-                args[1].as_str()
-            }
-            _ => DEFAULT_CONFIG_NAME
-        };
+        let config_name = crate::cli::config_name(&args).unwrap_or(DEFAULT_CONFIG_NAME);
         let res = Config::do_load_parse(config_name);
         info!("{:?}", res);
         res.unwrap_or_else(|e| {
@@ -53,8 +221,25 @@ impl Default for Config {
             template_pattern: "templates/**/*".to_string(),
             poem_template: "poems_77000.html".to_string(),
             problem_template: "problems.html".to_string(),
+            index_template: default_index_template(),
+            lookup_template: default_lookup_template(),
+            search_template: default_search_template(),
+            status_template: default_status_template(),
+            locale: default_locale(),
+            locale_dir: default_locale_dir(),
             src_dir: "data/src".to_string(),
             res_dir: "data/res".to_string(),
+            languages: default_languages(),
+            language_classes: Default::default(),
+            block_classes: Default::default(),
+            unknown_class_errors: false,
+            language_scripts: Default::default(),
+            rich_text_tags: Default::default(),
+            formats: default_formats(),
+            outputs: Default::default(),
+            export_json: false,
+            epub: Default::default(),
+            lint: Default::default(),
         }
     }
 }
@@ -82,6 +267,9 @@ mod tests {
         assert_eq!("problems.html".to_string(), CONFIG.problem_template);
         assert_eq!("data/src".to_string(), CONFIG.src_dir);
         assert_eq!("data/res".to_string(), CONFIG.res_dir);
+        assert_eq!(vec!["en", "ru"], CONFIG.languages);
+        assert_eq!(vec!["html"], CONFIG.formats);
+        assert_eq!("ru", CONFIG.locale);
     }
 
     #[test]
@@ -89,4 +277,67 @@ mod tests {
         let config: Config = toml::from_str(get_test_text().as_str()).unwrap();
         println!("{:#?}", config);
     }
+
+    #[test]
+    fn test_languages() {
+        let text = get_test_text() + r#"
+            languages = ['en', 'ru', 'uk']
+            [language_classes]
+            _7_poem-uk = 'uk'
+        "#;
+        let config: Config = toml::from_str(text.as_str()).unwrap();
+        assert_eq!(vec!["en", "ru", "uk"], config.languages);
+        assert_eq!("uk", config.language_classes["_7_poem-uk"]);
+
+        let text = get_test_text() + r#"
+            [block_classes]
+            _7_title = 'front_matter'
+            _7_note = 'footnote'
+        "#;
+        let config: Config = toml::from_str(text.as_str()).unwrap();
+        assert_eq!(BlockKind::Footnote, config.block_classes["_7_note"]);
+        assert!(toml::from_str::<Config>(&(get_test_text() + "[block_classes]\n_7_x = 'poem'\n")).is_err());
+    }
+
+    #[test]
+    fn test_outputs() {
+        let config: Config = toml::from_str(get_test_text().as_str()).unwrap();
+        let outputs = config.outputs();
+        assert_eq!(1, outputs.len());
+        assert_eq!("{source}.{ext}", outputs[0].file_name);
+
+        let text = get_test_text() + r#"
+            [[outputs]]
+            name = 'web'
+            template = 'poems_77000.html'
+            file_name = '{volume:02}.html'
+            [[outputs]]
+            name = 'print'
+            template = 'print.html'
+            file_name = 'print/{volume}.html'
+            enabled = false
+            [[outputs]]
+            name = 'text'
+            renderer = 'text'
+            file_name = 'text/{volume:02}.txt'
+        "#;
+        let config: Config = toml::from_str(text.as_str()).unwrap();
+        let outputs = config.outputs();
+        assert_eq!(vec!["web", "text"], outputs.iter().map(|o| o.name.as_str()).collect::<Vec<_>>());
+        assert_eq!("html", outputs[0].renderer);
+    }
+
+    #[test]
+    fn test_epub() {
+        let text = get_test_text() + r#"
+            [epub]
+            enabled = true
+            author = 'Sri Chinmoy'
+        "#;
+        let config: Config = toml::from_str(text.as_str()).unwrap();
+        assert!(config.epub.enabled);
+        assert!(!config.epub.collection);
+        assert_eq!("Sri Chinmoy", config.epub.author);
+        assert!(config.epub.title.is_empty());
+    }
 }
@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+
+use crate::book::Book;
+use crate::locale;
+use crate::poem::Poem;
+
+/// Строка в сравнении двух версий
+#[derive(Debug, PartialEq)]
+pub enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Отличие стихотворения в новой версии тома
+#[derive(Debug)]
+pub enum PoemDiff<'a> {
+    Added(&'a Poem),
+    Removed(&'a Poem),
+    /// Языки с изменёнными строками
+    Changed(&'a Poem, Vec<(String, Vec<Line<'a>>)>),
+}
+
+impl PoemDiff<'_> {
+    pub fn nn(&self) -> u32 {
+        match self {
+            Self::Added(p) | Self::Removed(p) | Self::Changed(p, _) => p.nn,
+        }
+    }
+}
+
+/// Построчное сравнение по наибольшей общей подпоследовательности
+pub fn diff_lines<'a>(old: &'a [String], new: &'a [String]) -> Vec<Line<'a>> {
+    let (n, m) = (old.len(), new.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old[i] == new[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    let mut res = Vec::with_capacity(n.max(m));
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old[i] == new[j] {
+            res.push(Line::Same(&old[i]));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            res.push(Line::Removed(&old[i]));
+            i += 1;
+        } else {
+            res.push(Line::Added(&new[j]));
+            j += 1;
+        }
+    }
+    res
+}
+
+/// Все стихотворения тома, включая неполные
+fn all_poems(book: &Book) -> BTreeMap<u32, &Poem> {
+    book.poems.iter().chain(&book.incomplete).map(|(nn, p)| (*nn, p)).collect()
+}
+
+/// Отличия `new` от `old` в порядке номеров стихотворений
+pub fn diff_books<'a>(old: &'a Book, new: &'a Book) -> Vec<PoemDiff<'a>> {
+    let old_poems = all_poems(old);
+    let new_poems = all_poems(new);
+    let mut res = Vec::new();
+    for (nn, old_poem) in &old_poems {
+        let Some(new_poem) = new_poems.get(nn) else {
+            res.push(PoemDiff::Removed(old_poem));
+            continue;
+        };
+        let mut langs = old_poem.languages().collect::<Vec<_>>();
+        langs.extend(new_poem.languages().filter(|l| !old_poem.text.contains_key(*l)));
+        let changed = langs.into_iter()
+            .filter(|lang| old_poem.lines(lang) != new_poem.lines(lang))
+            .map(|lang| (lang.to_string(), diff_lines(old_poem.lines(lang), new_poem.lines(lang))))
+            .collect::<Vec<_>>();
+        if !changed.is_empty() {
+            res.push(PoemDiff::Changed(new_poem, changed));
+        }
+    }
+    res.extend(new_poems.iter().filter(|(nn, _)| !old_poems.contains_key(nn)).map(|(_, p)| PoemDiff::Added(p)));
+    res.sort_by_key(PoemDiff::nn);
+    res
+}
+
+/// Команда `diff`: добавленные, удалённые и изменённые стихотворения
+pub fn run(old_name: &str, old: &Book, new_name: &str, new: &Book) {
+    println!("--- {} ({} {})", old_name, locale::msg("volume"), old.nn);
+    println!("+++ {} ({} {})", new_name, locale::msg("volume"), new.nn);
+    let diffs = diff_books(old, new);
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+    for diff in &diffs {
+        match diff {
+            PoemDiff::Added(poem) => {
+                added += 1;
+                println!("+ {}", poem.nn_str);
+            }
+            PoemDiff::Removed(poem) => {
+                removed += 1;
+                println!("- {}", poem.nn_str);
+            }
+            PoemDiff::Changed(poem, langs) => {
+                changed += 1;
+                println!("~ {}", poem.nn_str);
+                for (lang, lines) in langs {
+                    println!("    {}", lang);
+                    for line in lines {
+                        match line {
+                            Line::Same(l) => println!("        {}", l),
+                            Line::Removed(l) => println!("      - {}", l),
+                            Line::Added(l) => println!("      + {}", l),
+                        }
+                    }
+                }
+            }
+        }
+    }
+    println!("{}", locale::msg("diff_summary")
+        .replace("{added}", &added.to_string())
+        .replace("{removed}", &removed.to_string())
+        .replace("{changed}", &changed.to_string()));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::book::tests::get_test_book;
+    use crate::poem::tests::get_test_poem;
+
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('|').map(String::from).collect()
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let old = lines("a|b|c|d");
+        let new = lines("a|c|x|d");
+        assert_eq!(
+            vec![Line::Same("a"), Line::Removed("b"), Line::Same("c"), Line::Added("x"), Line::Same("d")],
+            diff_lines(&old, &new)
+        );
+        assert_eq!(vec![Line::Added("a")], diff_lines(&[], &lines("a")));
+        assert_eq!(vec![Line::Removed("a"), Line::Added("b")], diff_lines(&lines("a"), &lines("b")));
+    }
+
+    #[test]
+    fn test_diff_books() {
+        let old = get_test_book();
+        let mut new = get_test_book();
+        new.poems.remove(&2);
+        new.add(get_test_poem(8));
+        let mut poem = get_test_poem(5);
+        poem.text.get_mut("ru").unwrap()[1] = "Изменено".to_string();
+        new.add(poem);
+        // Неполное в новой версии - не удалено, но без одного языка
+        let mut poem = new.poems.remove(&6).unwrap();
+        poem.text.shift_remove("ru");
+        new.add_incomplete(poem);
+
+        let diffs = diff_books(&old, &new);
+        assert_eq!(vec![2, 5, 6, 8], diffs.iter().map(PoemDiff::nn).collect::<Vec<_>>());
+        assert!(matches!(diffs[0], PoemDiff::Removed(_)));
+        assert!(matches!(diffs[3], PoemDiff::Added(_)));
+        match &diffs[1] {
+            PoemDiff::Changed(_, langs) => {
+                assert_eq!(1, langs.len());
+                assert_eq!("ru", langs[0].0);
+                assert!(langs[0].1.contains(&Line::Added("Изменено")));
+            }
+            d => panic!("{:?}", d),
+        }
+        match &diffs[2] {
+            PoemDiff::Changed(_, langs) => assert!(langs[0].1.iter().all(|l| matches!(l, Line::Removed(_)))),
+            d => panic!("{:?}", d),
+        }
+        assert!(diff_books(&old, &get_test_book()).is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use serde::Serialize;
+use tera::Context;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::book::Book;
+use crate::config::{EpubConfig, CONFIG};
+use crate::poem::Poem;
+use crate::locale;
+use crate::render;
+use crate::template::TEMPLATES;
+use crate::utils;
+use crate::Result;
+
+/// Имя каталога с EPUB внутри `res_dir`
+pub static EPUB_DIR: &str = "epub";
+/// Имя файла EPUB со всеми томами
+pub static COLLECTION_FILE: &str = "collection.epub";
+
+static CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+    <rootfiles>
+        <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+    </rootfiles>
+</container>
+"#;
+
+/// Том внутри EPUB: файл с текстом и стихотворения для навигации
+#[derive(Serialize)]
+struct Volume<'a> {
+    nn: u32,
+    file: String,
+    poems: Vec<&'a Poem>,
+}
+
+impl<'a> Volume<'a> {
+    fn new(book: &'a Book) -> Self {
+        Self {
+            nn: book.nn,
+            file: format!("vol-{:02}.xhtml", book.nn),
+            poems: book.get_ordered_poems(),
+        }
+    }
+}
+
+/// "<dir>/Vol. 07.epub" для одного тома
+pub fn write_book_epub(dir: &Path, src_file_name: &str, book: &Book) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(src_file_name).with_extension("epub");
+    write_epub(&path, &format!("vol-{:02}", book.nn), &[book])
+}
+
+/// "<dir>/collection.epub" со всеми томами в порядке номеров
+pub fn write_collection_epub(dir: &Path, books: &[&Book]) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let mut books = books.to_vec();
+    books.sort_by_key(|b| b.nn);
+    write_epub(&dir.join(COLLECTION_FILE), "collection", &books)
+}
+
+/// Шаблоны, из которых собирается EPUB
+pub static TEMPLATE_NAMES: [&str; 4] = ["epub/content.opf", "epub/nav.xhtml", "epub/style.css", "epub/volume.xhtml"];
+
+/// `[epub]`, где незаданные название и автор взяты из сообщений локали
+fn metadata() -> EpubConfig {
+    let mut epub = CONFIG.epub.clone();
+    if epub.title.is_empty() {
+        epub.title = locale::MESSAGES.get("title").cloned().unwrap_or_default();
+    }
+    if epub.author.is_empty() {
+        epub.author = locale::MESSAGES.get("author").cloned().unwrap_or_default();
+    }
+    epub
+}
+
+fn context(id: &str, volumes: &[Volume]) -> Context {
+    let mut context = locale::context();
+    context.insert("epub", &metadata());
+    context.insert("languages", &CONFIG.languages);
+    context.insert("volumes", &volumes);
+    context.insert("identifier", &format!("urn:html-77000:{}", id));
+    context.insert("modified", &utils::now_iso8601());
+    context
+}
+
+/// Контекст любого из шаблонов EPUB для одного тома
+pub fn book_context(book: &Book) -> Context {
+    let volumes = [Volume::new(book)];
+    let mut context = context(&format!("vol-{:02}", book.nn), &volumes);
+    context.insert("book", book);
+    context.insert("books", &volumes[0].poems);
+    render::insert_blocks(&mut context, book);
+    context
+}
+
+fn write_epub(path: &Path, id: &str, books: &[&Book]) -> Result<()> {
+    let volumes = books.iter().map(|b| Volume::new(b)).collect::<Vec<_>>();
+    let mut context = context(id, &volumes);
+
+    let mut zip = ZipWriter::new(fs::File::create(path)?);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // mimetype обязан быть первым и несжатым
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(CONTAINER_XML.as_bytes())?;
+
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(TEMPLATES.render("epub/content.opf", &context)?.as_bytes())?;
+
+    zip.start_file("OEBPS/nav.xhtml", deflated)?;
+    zip.write_all(TEMPLATES.render("epub/nav.xhtml", &context)?.as_bytes())?;
+
+    let style = match &CONFIG.epub.stylesheet {
+        Some(stylesheet) => fs::read_to_string(stylesheet)?,
+        None => TEMPLATES.render("epub/style.css", &context)?,
+    };
+    zip.start_file("OEBPS/style.css", deflated)?;
+    zip.write_all(style.as_bytes())?;
+
+    for (book, volume) in books.iter().zip(&volumes) {
+        context.insert("book", book);
+        context.insert("books", &volume.poems);
+        render::insert_blocks(&mut context, book);
+        zip.start_file(format!("OEBPS/{}", volume.file), deflated)?;
+        zip.write_all(TEMPLATES.render("epub/volume.xhtml", &context)?.as_bytes())?;
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use zip::ZipArchive;
+
+    use crate::book::tests::get_test_book;
+
+    use super::*;
+
+    #[test]
+    fn test_write_book_epub() -> Result<()> {
+        let dir = std::env::temp_dir().join("html-77000-epub-test");
+        let book = get_test_book();
+        write_book_epub(&dir, "Vol. 07.html", &book)?;
+
+        let mut archive = ZipArchive::new(fs::File::open(dir.join("Vol. 07.epub"))?)?;
+        assert_eq!("mimetype", archive.by_index(0)?.name());
+
+        let mut opf = String::new();
+        archive.by_name("OEBPS/content.opf")?.read_to_string(&mut opf)?;
+        assert!(opf.contains("<dc:language>ru</dc:language>"));
+        assert!(opf.contains(r#"href="vol-07.xhtml""#));
+        assert!(opf.contains(&format!("<dc:creator>{}</dc:creator>", locale::MESSAGES["author"])));
+
+        let mut nav = String::new();
+        archive.by_name("OEBPS/nav.xhtml")?.read_to_string(&mut nav)?;
+        assert!(nav.contains(r##"href="vol-07.xhtml#p3""##));
+
+        let mut text = String::new();
+        archive.by_name("OEBPS/vol-07.xhtml")?.read_to_string(&mut text)?;
+        assert!(text.contains(r#"<p class="ru" xml:lang="ru" lang="ru">Йцукен 3-1<br/>"#));
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -24,12 +24,53 @@ pub enum Error {
     NoTranslationForPoem{
         number: u32,
     },
+    #[allow(non_camel_case_types)]
     CanNotAddLine_PoemHasNoNumber{
         line: String,
     },
     Html{
         html: String,
     },
+    UnknownLanguage{
+        number: u32,
+        lang: String,
+    },
+    /// Письменность раздела не совпадает с его языком
+    LanguageMismatch{
+        number: u32,
+        lang: String,
+        script: String,
+    },
+    /// Абзацы класса, который не разбирается, с `unknown_class_errors = true`
+    UnknownClass{
+        class: String,
+        count: usize,
+    },
+    /// Абзац номера, в котором нет номера стихотворения в ожидаемом виде
+    BadPoemNumber{
+        text: String,
+    },
+    VolumeNumberMismatch{
+        expected: u32,
+        found: u32,
+    },
+    PoemNumberMismatch{
+        key: u32,
+        number: u32,
+    },
+    UnknownFormat{
+        format: String,
+    },
+    BadFileNamePattern{
+        pattern: String,
+    },
+    Usage(String),
+    TemplateCheck{
+        problems: usize,
+    },
+    UnknownLocale{
+        locale: String,
+    },
 
     // -- Externals
     #[from]
@@ -47,6 +88,15 @@ pub enum Error {
 
     #[from]
     Parse(std::num::ParseIntError),
+
+    #[from]
+    Json(serde_json::Error),
+
+    #[from]
+    Zip(zip::result::ZipError),
+
+    #[from]
+    Regex(regex::Error),
 }
 
 // region:    --- Custom
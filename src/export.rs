@@ -0,0 +1,79 @@
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::book::Book;
+use crate::Result;
+
+/// Имя каталога с JSON-томами внутри `res_dir`
+pub static JSON_DIR: &str = "json";
+/// Имя файла со всеми томами (JSON Lines) внутри `res_dir`
+pub static JSONL_FILE: &str = "all_volumes.jsonl";
+
+/// Том целиком, в формате docs/json_export.md
+pub fn book_to_json(book: &Book) -> Result<String> {
+    Ok(serde_json::to_string_pretty(book)?)
+}
+
+/// "Vol. 07.html" -> "<dir>/Vol. 07.json"
+pub fn write_book_json(dir: &Path, src_file_name: &str, book: &Book) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(src_file_name).with_extension("json");
+    fs::write(path, book_to_json(book)?)?;
+    Ok(())
+}
+
+/// Все тома, по одному на строку, в порядке номеров томов
+pub fn write_books_jsonl(path: &Path, books: &[&Book]) -> Result<()> {
+    let mut file = BufWriter::new(fs::File::create(path)?);
+    let mut books = books.to_vec();
+    books.sort_by_key(|b| b.nn);
+    for book in books {
+        serde_json::to_writer(&mut file, book)?;
+        file.write_all(b"\n")?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+/// Тома из файла JSON Lines, записанного `write_books_jsonl`
+pub fn read_books_jsonl(path: &Path) -> Result<Vec<Book>> {
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| Ok(serde_json::from_str(l)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::book::tests::get_test_book;
+
+    use super::*;
+
+    #[test]
+    fn test_json_round_trip() -> Result<()> {
+        let book = get_test_book();
+        let json = book_to_json(&book)?;
+        let value: serde_json::Value = serde_json::from_str(&json)?;
+        assert_eq!(7, value["nn"]);
+        assert_eq!("Qwerty 3-1", value["poems"]["3"]["text"]["en"][0]);
+        assert_eq!("Йцукен 3-3", value["poems"]["3"]["text"]["ru"][2]);
+
+        let parsed: Book = serde_json::from_str(&json)?;
+        assert_eq!(book.poems.len(), parsed.poems.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_jsonl_round_trip() -> Result<()> {
+        let path = std::env::temp_dir().join("html-77000-test.jsonl");
+        let mut other = get_test_book();
+        other.nn = 3;
+        write_books_jsonl(&path, &[&get_test_book(), &other])?;
+        let books = read_books_jsonl(&path)?;
+        assert_eq!(vec![3, 7], books.iter().map(|b| b.nn).collect::<Vec<_>>());
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}
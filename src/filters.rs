@@ -0,0 +1,203 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use tera::{escape_html, from_value, to_value, Filter, Function, Value};
+
+use crate::collection::Volume;
+use crate::poem::Poem;
+use crate::utils::{plain_text, sanitize_rich_text};
+
+lazy_static! {
+    /// Все стихотворения запуска для функции `poem(nn=...)`: номер -> стихотворение с номером тома
+    static ref CORPUS: RwLock<BTreeMap<u32, Value>> = RwLock::new(BTreeMap::new());
+}
+
+/// Заполнение корпуса для `poem(nn=...)`; вызывается, когда прочитаны все тома
+pub fn set_corpus(volumes: &[Volume]) {
+    let mut corpus = CORPUS.write().unwrap();
+    corpus.clear();
+    for volume in volumes {
+        let links = volume.poem_links().unwrap_or_default();
+        for poem in volume.book.poems.values().chain(volume.book.incomplete.values()) {
+            if let Ok(mut value) = to_value(poem) {
+                value["volume"] = Value::from(volume.book.nn);
+                value["link"] = Value::from(links.get(&poem.nn).map(|l| format!("{}#{}", l, poem.nn)));
+                corpus.insert(poem.nn, value);
+            }
+        }
+    }
+}
+
+fn arg_str<'a>(args: &'a HashMap<String, Value>, name: &str, default: &'a str) -> &'a str {
+    args.get(name).and_then(Value::as_str).unwrap_or(default)
+}
+
+/// `{{ 1234567 | poem_number }}` -> "1 234 567", `{{ n | poem_number(sep=",") }}` -> "1,234,567"
+pub fn poem_number(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let nn = from_value::<u32>(value.clone())
+        .map_err(|_| tera::Error::msg(format!("poem_number: expected a number, got {}", value)))?;
+    Ok(Value::from(Poem::nn_str(nn, arg_str(args, "sep", " "))))
+}
+
+/// `{{ 7 | roman }}` -> "VII"
+pub fn roman(value: &Value, _args: &HashMap<String, Value>) -> tera::Result<Value> {
+    static DIGITS: [(u64, &str); 13] = [
+        (1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"),
+        (50, "L"), (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I"),
+    ];
+    let mut n = match value.as_u64() {
+        Some(n @ 1..=3999) => n,
+        _ => return Err(tera::Error::msg(format!("roman: expected a number from 1 to 3999, got {}", value))),
+    };
+    let mut res = String::new();
+    for (d, s) in DIGITS {
+        while n >= d {
+            res.push_str(s);
+            n -= d;
+        }
+    }
+    Ok(Value::from(res))
+}
+
+/// `{{ book | volume_range }}` -> "6 001–6 999": первый и последний номер тома.
+/// Принимает `Book` (по ключам `poems` и `incomplete`) или строку оглавления с `first`/`last`;
+/// аргументы `sep` - как у `poem_number`, `dash` - разделитель номеров
+pub fn volume_range(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let range = match (value.get("first").and_then(Value::as_u64), value.get("last").and_then(Value::as_u64)) {
+        (Some(first), Some(last)) => Some((first, last)),
+        _ => {
+            let poems = value.get("poems").and_then(Value::as_object)
+                .ok_or_else(|| tera::Error::msg("volume_range: expected a book or a volume summary"))?;
+            let incomplete = value.get("incomplete").and_then(Value::as_object);
+            let numbers = poems.keys()
+                .chain(incomplete.into_iter().flat_map(|m| m.keys()))
+                .filter_map(|k| k.parse::<u64>().ok())
+                .collect::<Vec<_>>();
+            numbers.iter().min().zip(numbers.iter().max()).map(|(a, b)| (*a, *b))
+        }
+    };
+    let Some((first, last)) = range else {
+        return Ok(Value::from(""));
+    };
+    let sep = arg_str(args, "sep", " ");
+    let first = Poem::nn_str(first as u32, sep);
+    if first == Poem::nn_str(last as u32, sep) {
+        return Ok(Value::from(first));
+    }
+    Ok(Value::from(format!("{}{}{}", first, arg_str(args, "dash", "–"), Poem::nn_str(last as u32, sep))))
+}
+
+/// Строки (массив, строка или null), каждая преобразованная `f`, через `sep` (по умолчанию `<br>`)
+fn join_lines(name: &str, value: &Value, args: &HashMap<String, Value>, f: fn(&str) -> String) -> tera::Result<Value> {
+    let lines = match value {
+        Value::Array(lines) => lines.iter().map(|l| l.as_str().map(f)).collect::<Option<Vec<_>>>(),
+        Value::String(line) => Some(vec![f(line)]),
+        Value::Null => Some(vec![]),
+        _ => None,
+    }.ok_or_else(|| tera::Error::msg(format!("{}: expected lines, got {}", name, value)))?;
+    Ok(Value::from(lines.join(arg_str(args, "sep", "<br>"))))
+}
+
+/// `{{ b.text.en | lines_to_html }}` -> строки без тегов форматирования, с экранированием,
+/// через `<br>` (аргумент `sep`)
+pub struct LinesToHtml;
+
+impl Filter for LinesToHtml {
+    fn filter(&self, value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        join_lines("lines_to_html", value, args, |l| escape_html(&plain_text(l)))
+    }
+
+    /// Результат уже экранирован
+    fn is_safe(&self) -> bool {
+        true
+    }
+}
+
+/// `{{ b.text.en | rich_text }}` - как `lines_to_html`, но теги из `rich_text_tags`
+/// остаются разметкой (без атрибутов, с закрытием незакрытых)
+pub struct RichText;
+
+impl Filter for RichText {
+    fn filter(&self, value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        join_lines("rich_text", value, args, sanitize_rich_text)
+    }
+
+    /// Результат уже экранирован
+    fn is_safe(&self) -> bool {
+        true
+    }
+}
+
+/// `{% set p = poem(nn=1234) %}` - стихотворение из любого тома (с полями `volume` и `link` -
+/// страница тома относительно `res_dir` с якорем) или null
+pub struct PoemLookup;
+
+impl Function for PoemLookup {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let nn = args.get("nn").and_then(Value::as_u64)
+            .ok_or_else(|| tera::Error::msg("poem: expected argument nn=<number>"))?;
+        let corpus = CORPUS.read().map_err(|e| tera::Error::msg(e.to_string()))?;
+        Ok(u32::try_from(nn).ok().and_then(|nn| corpus.get(&nn)).cloned().unwrap_or(Value::Null))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tera::{Context, Tera};
+
+    use crate::book::tests::get_test_book;
+    use crate::poem::tests::get_test_poem;
+
+    use super::*;
+
+    fn render(template: &str, context: &Context) -> tera::Result<String> {
+        let mut tera = Tera::default();
+        tera.register_filter("poem_number", poem_number);
+        tera.register_filter("roman", roman);
+        tera.register_filter("volume_range", volume_range);
+        tera.register_filter("lines_to_html", LinesToHtml);
+        tera.register_filter("rich_text", RichText);
+        tera.register_function("poem", PoemLookup);
+        tera.render_str(template, context)
+    }
+
+    #[test]
+    fn test_filters() -> tera::Result<()> {
+        let mut context = Context::new();
+        context.insert("book", &get_test_book());
+        context.insert("lines", &vec!["a < b", "Tom & Jerry"]);
+
+        assert_eq!("1 234 567", render("{{ 1234567 | poem_number }}", &context)?);
+        assert_eq!("1,234", render(r#"{{ 1234 | poem_number(sep=",") }}"#, &context)?);
+        assert_eq!("XLVIII", render("{{ 48 | roman }}", &context)?);
+        assert!(render("{{ 0 | roman }}", &context).is_err());
+        assert_eq!("1–7", render("{{ book | volume_range }}", &context)?);
+        assert_eq!("6 001 - 6 999", render(r#"{{ v | volume_range(dash=" - ") }}"#, &{
+            let mut c = Context::new();
+            c.insert("v", &serde_json::json!({"first": 6001, "last": 6999}));
+            c
+        })?);
+        assert_eq!("a &lt; b<br>Tom &amp; Jerry", render("{{ lines | lines_to_html }}", &context)?);
+
+        // Неполные стихотворения тоже входят в диапазон
+        let mut book = get_test_book();
+        book.add_incomplete(get_test_poem(9));
+        context.insert("book", &book);
+        assert_eq!("1–9", render("{{ book | volume_range }}", &context)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_poem_lookup() -> tera::Result<()> {
+        let mut book = get_test_book();
+        book.add_incomplete(get_test_poem(9));
+        set_corpus(&[Volume::new("Vol. 07.html", book)]);
+        let context = Context::new();
+        assert_eq!("7: Qwerty 3-1", render("{% set p = poem(nn=3) %}{{ p.volume }}: {{ p.text.en[0] }}", &context)?);
+        assert_eq!("Vol. 07.html#3", render("{% set p = poem(nn=3) %}{{ p.link }}", &context)?);
+        assert_eq!("7: Qwerty 9-1", render("{% set p = poem(nn=9) %}{{ p.volume }}: {{ p.text.en[0] }}", &context)?);
+        assert_eq!("none", render("{% set p = poem(nn=100) %}{% if p %}{{ p.nn }}{% else %}none{% endif %}", &context)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,366 @@
+use std::fmt;
+use std::ops::Range;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::book::Book;
+use crate::poem::Poem;
+use crate::utils::{plain_text, rich_tag_ranges};
+
+/// Правила: код -> описание. Код можно отключить в `[lint] disabled`
+pub static RULES: [(&str, &str); 14] = [
+    ("RU001", "прямые кавычки вместо «ёлочек»"),
+    ("RU002", "дефис вместо тире"),
+    ("RU003", "двойной пробел"),
+    ("RU004", "стихотворение начинается со строчной буквы"),
+    ("RU005", "латинская буква в русском слове"),
+    ("RU006", "знак в конце стихотворения не совпадает с английским"),
+    ("RU007", "обычный пробел перед тире вместо неразрывного"),
+    ("RU008", "пробел в начале или в конце строки"),
+    ("EN001", "прямые кавычки или апостроф вместо типографских"),
+    ("EN002", "пробел перед знаком препинания"),
+    ("EN003", "повторённое слово"),
+    ("EN004", "кириллица в английской строке"),
+    ("EN005", "двойной пробел"),
+    ("EN006", "пробел в начале или в конце строки"),
+];
+
+lazy_static! {
+    static ref RE_HYPHEN_DASH: Regex = Regex::new(r"(^|\s)(-|--)(\s|$)").unwrap();
+    static ref RE_WORD: Regex = Regex::new(r"[\w'’]+").unwrap();
+    static ref RE_SPACE_PUNCT: Regex = Regex::new(r"\s([,;:!?]|\.([^.]|$))").unwrap();
+    static ref RE_SPACES: Regex = Regex::new(r" {2,}").unwrap();
+}
+
+/// Замечание к строке стихотворения
+#[derive(Debug, PartialEq)]
+pub struct Warning {
+    pub rule: &'static str,
+    pub nn: u32,
+    pub lang: String,
+    /// Номер строки, с 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}:{}: {}", self.rule, self.nn, self.lang, self.line, self.message)
+    }
+}
+
+/// Исправление строки стихотворения (`build --fix`)
+#[derive(Debug, PartialEq)]
+pub struct Fix {
+    pub rule: &'static str,
+    pub nn: u32,
+    pub lang: String,
+    /// Номер строки, с 1
+    pub line: usize,
+    pub before: String,
+    pub after: String,
+}
+
+impl fmt::Display for Fix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}:{}: {} -> {}", self.rule, self.nn, self.lang, self.line, self.before, self.after)
+    }
+}
+
+/// Коды из `disabled`, которых нет в `RULES`
+pub fn unknown_rules(disabled: &[String]) -> Vec<&str> {
+    disabled.iter().map(String::as_str).filter(|d| !RULES.iter().any(|(code, _)| code == d)).collect()
+}
+
+/// Замечания ко всем стихотворениям тома, включая неполные, кроме правил `disabled`
+pub fn lint_book(book: &Book, disabled: &[String]) -> Vec<Warning> {
+    let mut poems = book.poems.values().chain(book.incomplete.values()).collect::<Vec<_>>();
+    poems.sort_by_key(|p| p.nn);
+    poems.into_iter()
+        .flat_map(lint_poem)
+        .filter(|w| !disabled.iter().any(|d| d == w.rule))
+        .collect()
+}
+
+fn lint_poem(poem: &Poem) -> Vec<Warning> {
+    let mut res = Vec::new();
+    let mut warn = |rule: &'static str, lang: &str, line: usize, message: String| {
+        res.push(Warning { rule, nn: poem.nn, lang: lang.to_string(), line, message });
+    };
+    let lines = |lang: &str| poem.lines(lang).iter().map(|l| plain_text(l).into_owned()).collect::<Vec<_>>();
+
+    let en = lines("en");
+    for (i, line) in en.iter().enumerate() {
+        if line.contains(['"', '\'']) {
+            warn("EN001", "en", i + 1, format!("прямые кавычки: {}", line));
+        }
+        if RE_SPACE_PUNCT.is_match(line) {
+            warn("EN002", "en", i + 1, format!("пробел перед знаком: {}", line));
+        }
+        if line.trim().contains("  ") {
+            warn("EN005", "en", i + 1, format!("двойной пробел: {}", line));
+        }
+        if line.trim() != line {
+            warn("EN006", "en", i + 1, format!("пробел по краям: «{}»", line));
+        }
+        // Только через пробел: "Light, light" - повтор намеренный
+        let words = RE_WORD.find_iter(line).collect::<Vec<_>>();
+        for pair in words.windows(2) {
+            let (a, b) = (pair[0].as_str(), pair[1].as_str());
+            if a.to_lowercase() == b.to_lowercase() && line[pair[0].end()..pair[1].start()].trim().is_empty() {
+                warn("EN003", "en", i + 1, format!("«{} {}»", a, b));
+            }
+        }
+        if line.chars().any(is_cyrillic) {
+            warn("EN004", "en", i + 1, format!("кириллица: {}", line));
+        }
+    }
+
+    let ru = lines("ru");
+    for (i, line) in ru.iter().enumerate() {
+        if line.contains(['"', '“', '”', '„']) {
+            warn("RU001", "ru", i + 1, format!("прямые кавычки: {}", line));
+        }
+        if RE_HYPHEN_DASH.is_match(line) {
+            warn("RU002", "ru", i + 1, format!("дефис вместо тире: {}", line));
+        }
+        if line.trim().contains("  ") {
+            warn("RU003", "ru", i + 1, format!("двойной пробел: {}", line));
+        }
+        if line.trim() != line {
+            warn("RU008", "ru", i + 1, format!("пробел по краям: «{}»", line));
+        }
+        if line.contains(" —") {
+            warn("RU007", "ru", i + 1, format!("обычный пробел перед тире: {}", line));
+        }
+        for word in line.split(|c: char| !c.is_alphanumeric()) {
+            if word.chars().any(is_cyrillic) && word.chars().any(|c| c.is_ascii_alphabetic()) {
+                warn("RU005", "ru", i + 1, format!("латинская буква в слове «{}»", word));
+            }
+        }
+    }
+    if let Some(c) = ru.iter().flat_map(|l| l.chars()).find(|c| c.is_alphabetic()) {
+        if c.is_lowercase() {
+            warn("RU004", "ru", 1, format!("строчная буква в начале: {}", ru[0]));
+        }
+    }
+    if let (Some(ru_mark), Some(en_mark)) = (final_mark(&ru), final_mark(&en)) {
+        if ru_mark != en_mark {
+            warn("RU006", "ru", ru.len(), format!("в конце «{}», в английском «{}»", ru_mark, en_mark));
+        }
+    }
+    res
+}
+
+type FixFn = fn(&str) -> String;
+
+/// Безопасные исправления по языкам, в порядке применения
+static RU_FIXES: [(&str, FixFn); 5] = [
+    ("RU008", fix_trim),
+    ("RU003", fix_spaces),
+    ("RU001", fix_ru_quotes),
+    ("RU002", fix_hyphen_dash),
+    ("RU007", fix_dash_space),
+];
+static EN_FIXES: [(&str, FixFn); 4] = [
+    ("EN006", fix_trim),
+    ("EN005", fix_spaces),
+    ("EN002", fix_space_punct),
+    ("EN001", fix_en_quotes),
+];
+
+/// Безопасные исправления во всех стихотворениях тома, кроме правил `disabled`
+pub fn fix_book(book: &mut Book, disabled: &[String]) -> Vec<Fix> {
+    let mut res = Vec::new();
+    for poem in book.poems.values_mut().chain(book.incomplete.values_mut()) {
+        for (lang, lines) in poem.text.iter_mut() {
+            let fixes = match lang.as_str() {
+                "ru" => RU_FIXES.as_slice(),
+                "en" => EN_FIXES.as_slice(),
+                _ => continue,
+            };
+            for (i, line) in lines.iter_mut().enumerate() {
+                for (rule, fix) in fixes.iter().filter(|(rule, _)| !disabled.iter().any(|d| d == rule)) {
+                    let after = fix(line);
+                    if after != *line {
+                        let before = std::mem::replace(line, after.clone());
+                        res.push(Fix { rule, nn: poem.nn, lang: lang.clone(), line: i + 1, before, after });
+                    }
+                }
+            }
+        }
+    }
+    res.sort_by_key(|f| f.nn);
+    res
+}
+
+/// Подряд идущие пробелы внутри строки - один; края не трогаются, это `fix_trim`
+fn fix_spaces(line: &str) -> String {
+    let trimmed = line.trim();
+    let start = line.len() - line.trim_start().len();
+    format!("{}{}{}", &line[..start], RE_SPACES.replace_all(trimmed, " "), &line[start + trimmed.len()..])
+}
+
+fn fix_trim(line: &str) -> String {
+    line.trim().to_string()
+}
+
+/// " - " -> неразрывный пробел и тире, "- " в начале строки -> "— "
+fn fix_hyphen_dash(line: &str) -> String {
+    RE_HYPHEN_DASH.replace_all(line, |caps: &regex::Captures| {
+        let space = if caps[1].is_empty() { "" } else { "\u{a0}" };
+        format!("{}—{}", space, &caps[3])
+    }).into_owned()
+}
+
+fn fix_dash_space(line: &str) -> String {
+    line.replace(" —", "\u{a0}—")
+}
+
+fn fix_space_punct(line: &str) -> String {
+    RE_SPACE_PUNCT.replace_all(line, "$1").into_owned()
+}
+
+fn fix_ru_quotes(line: &str) -> String {
+    fix_quotes(line, &rich_tag_ranges(line), '"', '«', '»')
+}
+
+fn fix_en_quotes(line: &str) -> String {
+    let line = fix_quotes(line, &rich_tag_ranges(line), '"', '“', '”');
+    fix_quotes(&line, &rich_tag_ranges(&line), '\'', '‘', '’')
+}
+
+/// Прямая кавычка `straight` -> открывающая или закрывающая. Открывающая - после
+/// начала строки, пробела или скобки, закрывающая - перед концом строки, пробелом
+/// или знаком препинания, иначе по очереди. Апостроф внутри слова - закрывающая.
+/// Теги форматирования (`tags`, диапазоны в байтах) не учитываются; прочие `<` и `>` - текст
+fn fix_quotes(line: &str, tags: &[Range<usize>], straight: char, open: char, close: char) -> String {
+    let chars = line.chars().collect::<Vec<_>>();
+    let visible = line.char_indices().map(|(i, _)| !tags.iter().any(|t| t.contains(&i))).collect::<Vec<_>>();
+    let prev = |i: usize| (0..i).rev().find(|j| visible[*j]).map(|j| chars[j]);
+    let next = |i: usize| (i + 1..chars.len()).find(|j| visible[*j]).map(|j| chars[j]);
+
+    let mut opened = false;
+    let mut res = String::with_capacity(line.len());
+    for (i, c) in chars.iter().enumerate() {
+        if *c != straight || !visible[i] {
+            res.push(*c);
+            continue;
+        }
+        let (prev, next) = (prev(i), next(i));
+        let is_open = match (prev, next) {
+            (Some(p), Some(n)) if p.is_alphanumeric() && n.is_alphanumeric() => false,
+            (None, _) => true,
+            (Some(p), _) if p.is_whitespace() || "([{«—-".contains(p) => true,
+            (_, None) => false,
+            (_, Some(n)) if n.is_whitespace() || ".,;:!?)]}…".contains(n) => false,
+            _ => !opened,
+        };
+        opened = is_open;
+        res.push(if is_open { open } else { close });
+    }
+    res
+}
+
+pub fn is_cyrillic(c: char) -> bool {
+    matches!(c, '\u{0400}'..='\u{04FF}')
+}
+
+/// Знак препинания в конце последней непустой строки (без закрывающих кавычек и скобок);
+/// "..." и "…" - одно и то же, отсутствие знака - пробел. `None` - строк нет
+fn final_mark(lines: &[String]) -> Option<char> {
+    let line = lines.iter().rev().map(|l| l.trim()).find(|l| !l.is_empty())?;
+    let line = line.trim_end_matches(['»', '"', '”', '’', '\'', ')', ']']);
+    if line.ends_with("...") {
+        return Some('…');
+    }
+    Some(line.chars().last().filter(|c| ".!?…,;:".contains(*c)).unwrap_or(' '))
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+
+    use super::*;
+
+    fn poem(en: &[&str], ru: &[&str]) -> Poem {
+        let mut text = IndexMap::new();
+        text.insert("en".to_string(), en.iter().map(|l| l.to_string()).collect());
+        text.insert("ru".to_string(), ru.iter().map(|l| l.to_string()).collect());
+        Poem::new(6001, text)
+    }
+
+    fn rules(poem: &Poem) -> Vec<(&'static str, usize)> {
+        lint_poem(poem).iter().map(|w| (w.rule, w.line)).collect()
+    }
+
+    #[test]
+    fn test_clean() {
+        assert!(rules(&poem(&["My Lord, my life."], &["Господь мой\u{a0}— жизнь моя.", "«Всегда»."])).is_empty());
+    }
+
+    #[test]
+    fn test_russian_rules() {
+        assert_eq!(vec![("RU001", 1)], rules(&poem(&["Yes."], &["Он сказал: \"Да\"."])));
+        assert_eq!(vec![("RU002", 1), ("RU002", 2)], rules(&poem(&["Yes."], &["Жизнь - свет", "- Да."])));
+        assert!(rules(&poem(&["Yes."], &["Кто-то пришёл."])).is_empty());
+        assert_eq!(vec![("RU003", 1)], rules(&poem(&["Yes."], &["Жизнь  свет."])));
+        assert_eq!(vec![("RU004", 1)], rules(&poem(&["Yes."], &["«жизнь свет."])));
+        // "о" в "Слово" - латинская
+        assert_eq!(vec![("RU005", 1)], rules(&poem(&["Yes."], &["Слoво."])));
+        assert_eq!(vec![("RU006", 2)], rules(&poem(&["Yes!"], &["Да,", "да."])));
+        assert!(rules(&poem(&["Yes..."], &["«Да…»"])).is_empty());
+        assert_eq!(vec![("RU007", 1)], rules(&poem(&["Yes."], &["Жизнь — свет."])));
+        assert_eq!(vec![("RU008", 1)], rules(&poem(&["Yes."], &["Жизнь свет. "])));
+    }
+
+    #[test]
+    fn test_english_rules() {
+        assert_eq!(vec![("EN001", 1), ("EN001", 2)], rules(&poem(&["God's light", "\"Yes\"."], &["Свет Бога", "«Да»."])));
+        assert!(rules(&poem(&["God’s “light”."], &["Свет «Бога»."])).is_empty());
+        assert_eq!(vec![("EN002", 1), ("EN002", 2)], rules(&poem(&["Light , love", "Yes ."], &["Свет, любовь", "Да."])));
+        assert!(rules(&poem(&["Wait ...", "and see."], &["Жди…", "и смотри."])).is_empty());
+        assert_eq!(vec![("EN003", 1)], rules(&poem(&["The the light."], &["Свет."])));
+        assert!(rules(&poem(&["Light, light everywhere."], &["Свет, свет везде."])).is_empty());
+        assert_eq!(vec![("EN005", 1)], rules(&poem(&["Light  love."], &["Свет, любовь."])));
+        assert_eq!(vec![("EN006", 1)], rules(&poem(&[" Light love."], &["Свет, любовь."])));
+        assert_eq!(vec![("EN004", 1)], rules(&poem(&["Light и love."], &["Свет и любовь."])));
+    }
+
+    #[test]
+    fn test_fix() {
+        assert_eq!("Он сказал: «Да».", fix_ru_quotes("Он сказал: \"Да\"."));
+        assert_eq!("«<i>Да</i>»", fix_quotes("\"<i>Да</i>\"", &[1..4, 8..12], '"', '«', '»'));
+        // Без `rich_text_tags` "<" - просто знак и не скрывает кавычки после себя
+        assert_eq!("1 < 2, «да»", fix_ru_quotes("1 < 2, \"да\""));
+        assert_eq!("Жизнь\u{a0}— свет", fix_hyphen_dash("Жизнь - свет"));
+        assert_eq!("— Да.", fix_hyphen_dash("- Да."));
+        assert_eq!("Кто-то", fix_hyphen_dash("Кто-то"));
+        assert_eq!("Жизнь\u{a0}— свет", fix_dash_space("Жизнь — свет"));
+        assert_eq!("  a b c ", fix_spaces("  a  b c "));
+        assert_eq!("a  b c", fix_trim("  a  b c "));
+        assert_eq!("Light, love. Wait ...", fix_space_punct("Light , love . Wait ..."));
+        assert_eq!("God’s “light” and ‘love’", fix_en_quotes("God's \"light\" and 'love'"));
+
+        let mut book = Book::new(7);
+        book.add(poem(&["Light , love"], &["Жизнь  -  \"свет\"."]));
+        let fixes = fix_book(&mut book, &["RU007".to_string()]);
+        assert_eq!(vec!["EN002", "RU003", "RU001", "RU002"], fixes.iter().map(|f| f.rule).collect::<Vec<_>>());
+        assert_eq!("Жизнь - \"свет\".", fixes[1].after);
+        assert_eq!("Жизнь\u{a0}— «свет».", book.poems[&6001].lines("ru")[0]);
+        assert_eq!("Light, love", book.poems[&6001].lines("en")[0]);
+        assert_eq!("EN002 6001 en:1: Light , love -> Light, love", fixes[0].to_string());
+        assert!(lint_book(&book, &[]).iter().all(|w| w.rule == "RU006"));
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut book = Book::new(7);
+        book.add(poem(&["Yes!"], &["да  нет."]));
+        let disabled = ["RU003".to_string(), "RU006".to_string(), "RU999".to_string()];
+        assert_eq!(vec!["RU999"], unknown_rules(&disabled));
+        let warnings = lint_book(&book, &disabled);
+        assert_eq!(vec!["RU004 6001 ru:1: строчная буква в начале: да  нет."], warnings.iter().map(|w| w.to_string()).collect::<Vec<_>>());
+    }
+}
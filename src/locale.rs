@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use tera::Context;
+
+use crate::config::CONFIG;
+use crate::{Error, Result};
+
+/// Встроенные файлы сообщений: имя файла -> текст TOML
+pub static DEFAULT_LOCALES: [(&str, &str); 2] = [
+    ("ru.toml", include_str!("../locales/ru.toml")),
+    ("en.toml", include_str!("../locales/en.toml")),
+];
+
+lazy_static! {
+    /// Сообщения локали `locale` из конфигурации
+    pub static ref MESSAGES: BTreeMap<String, String> = {
+        match load_messages(CONFIG.locale.as_str(), Path::new(CONFIG.locale_dir.as_str())) {
+            Ok(m) => m,
+            Err(e) => {
+                println!("Loading messages error: {:?}", e);
+                ::std::process::exit(1);
+            }
+        }
+    };
+}
+
+/// Сообщения "<dir>/<locale>.toml" поверх встроенных для той же локали;
+/// локаль без встроенного файла требует свой файл
+pub fn load_messages(locale: &str, dir: &Path) -> Result<BTreeMap<String, String>> {
+    let file_name = format!("{}.toml", locale);
+    let mut res = match DEFAULT_LOCALES.iter().find(|(name, _)| *name == file_name) {
+        Some((_, text)) => toml::from_str(text)?,
+        None => BTreeMap::new(),
+    };
+    match fs::read_to_string(dir.join(&file_name)) {
+        Ok(text) => res.extend(toml::from_str::<BTreeMap<String, String>>(&text)?),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            if res.is_empty() {
+                return Err(Error::UnknownLocale { locale: locale.to_string() });
+            }
+        }
+        Err(e) => return Err(e.into()),
+    }
+    Ok(res)
+}
+
+/// Сообщение локали для вывода команд; нет в файле локали - сам ключ
+pub fn msg(key: &str) -> &str {
+    MESSAGES.get(key).map_or(key, String::as_str)
+}
+
+/// Начальный контекст любого шаблона: `msg` и `locale`
+pub fn context() -> Context {
+    let mut context = Context::new();
+    context.insert("msg", &*MESSAGES);
+    context.insert("locale", &CONFIG.locale);
+    context
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_messages() -> Result<()> {
+        let dir = std::env::temp_dir().join("html-77000-locale-test");
+        // Файлы, оставшиеся от прерванного запуска, изменили бы результат
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+
+        let ru = load_messages("ru", &dir)?;
+        assert_eq!("Часть", ru["volume"]);
+        // У встроенных локалей одинаковые ключи
+        assert_eq!(ru.keys().collect::<Vec<_>>(), load_messages("en", &dir)?.keys().collect::<Vec<_>>());
+
+        fs::write(dir.join("ru.toml"), "volume = 'Том'\nextra = 'Ещё'\n")?;
+        let ru = load_messages("ru", &dir)?;
+        assert_eq!("Том", ru["volume"]);
+        assert_eq!("Ещё", ru["extra"]);
+        assert_eq!("Поиск", ru["search"]);
+
+        assert!(matches!(load_messages("uk", &dir), Err(Error::UnknownLocale { .. })));
+        fs::write(dir.join("uk.toml"), "volume = 'Частина'\n")?;
+        assert_eq!("Частина", load_messages("uk", &dir)?["volume"]);
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
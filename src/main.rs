@@ -1,38 +1,104 @@
 use std::fs;
 use std::io::{BufWriter, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
 
 use report::BookReport;
 use scraper::{Html, Selector};
-use tera::Context;
 use threadpool::ThreadPool;
-use tracing::info;
+use tracing::{error, info, warn, Level};
 
 pub use config::*;
 pub use error::*;
 
 use crate::book::Book;
 use crate::book_builder::BookBuilder;
-use crate::template::TEMPLATES;
+use crate::cli::Command;
+use crate::collection::{Navigation, Pagination, Volume};
 use crate::utils::*;
 
 pub mod poem;
 pub mod book;
 pub mod tmp_poem;
 mod book_builder;
+mod check;
+mod cli;
+mod collection;
 mod template;
 mod utils;
 mod config;
+mod diff;
 mod error;
+mod epub;
+mod export;
+mod filters;
+mod lint;
+mod locale;
+mod render;
 pub mod report;
+mod script;
+mod search;
+mod status;
 
 fn main()->Result<()> {
-    init_logger();
+    let args = std::env::args().collect::<Vec<String>>();
+    let command = match cli::parse_command(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}", if let Error::Usage(usage) = &e { usage.clone() } else { e.to_string() });
+            std::process::exit(2);
+        }
+    };
 
-    let pool = ThreadPool::new(100);
-    // let mut file_count = 0;
+    match command {
+        Command::Build { fix } => {
+            init_logger(Level::TRACE);
+            build(fix)
+        }
+        Command::Search(args) => {
+            init_logger(Level::WARN);
+            let volumes = if args.cached {
+                let path = join_file_path(CONFIG.res_dir.as_str(), export::JSONL_FILE);
+                export::read_books_jsonl(&path)?.into_iter().map(|b| Volume::new("", b)).collect()
+            } else {
+                read_volumes(&ThreadPool::new(100), |path| Ok(read_file(path, false)?.0))?
+            };
+            search::run(&args, &volumes)
+        }
+        Command::Status { json } => {
+            init_logger(Level::WARN);
+            let volumes = read_volumes(&ThreadPool::new(100), |path| Ok(read_file(path, false)?.0))?;
+            status::run(&volumes, json)
+        }
+        Command::CheckTemplates => {
+            init_logger(Level::WARN);
+            check::run()
+        }
+        Command::Diff { old, new } => {
+            init_logger(Level::WARN);
+            let book_num = diff_book_num(&old, &new);
+            let old_book = read_book(PathBuf::from(&old), book_num)?;
+            let new_book = read_book(PathBuf::from(&new), book_num)?;
+            diff::run(&old, &old_book, &new, &new_book);
+            Ok(())
+        }
+        Command::Init { force } => {
+            init_logger(Level::WARN);
+            template::write_defaults(&template::template_dir(&CONFIG.template_pattern), &template::DEFAULT_TEMPLATES, force)?;
+            template::write_defaults(Path::new(CONFIG.locale_dir.as_str()), &locale::DEFAULT_LOCALES, force)
+        }
+    }
+}
 
-    prepare_res_dir(CONFIG.res_dir.as_str())?;
+/// Чтение всех исходных файлов из `src_dir` в пуле потоков; тома упорядочены по номеру
+fn read_volumes<F>(pool: &ThreadPool, read: F) -> Result<Vec<Volume>>
+where
+    F: Fn(PathBuf) -> Result<Option<Volume>> + Send + Sync + 'static,
+{
+    let read = Arc::new(read);
+    let (tx, rx) = channel();
+    // let mut file_count = 0;
 
     for entry in fs::read_dir(CONFIG.src_dir.as_str())? {
         let path = entry?.path();
@@ -40,77 +106,213 @@ fn main()->Result<()> {
             // file_count += 1;
 
             {
+                let tx = tx.clone();
+                let read = read.clone();
                 pool.execute(move || {
-                    process_file(path, CONFIG.res_dir.as_str()).unwrap();
+                    // Ошибка одного файла не должна терять остальные тома: в журнал, том пропускается
+                    let volume = read(path.clone()).unwrap_or_else(|e| {
+                        error!("{}: {:?}", path.display(), e);
+                        None
+                    });
+                    tx.send(volume).unwrap();
                 });
             }
         }
     }
+    drop(tx);
 
     pool.join();
     //assert_eq!(rx.iter().take(file_count).fold(0, |a, b| a + b), file_count);
+    let mut volumes = rx.iter().flatten().collect::<Vec<Volume>>();
+    volumes.sort_by_key(|v| v.book.nn);
+    Ok(volumes)
+}
+
+/// Разбор всех томов и запись всех выходов; с `fix` - с типографскими исправлениями
+fn build(fix: bool) -> Result<()> {
+    let pool = ThreadPool::new(100);
+
+    prepare_res_dir(CONFIG.res_dir.as_str())?;
+    for code in lint::unknown_rules(&CONFIG.lint.disabled) {
+        warn!("Unknown lint rule in [lint] disabled: {}", code);
+    }
+
+    let volumes = read_volumes(&pool, move |path| process_file(path, fix))?;
+    filters::set_corpus(&volumes);
+
+    // Все тома прочитаны - можно выводить каждый со ссылками на соседей
+    let volumes = Arc::new(volumes);
+    for i in 0..volumes.len() {
+        let volumes = volumes.clone();
+        pool.execute(move || {
+            if let Err(e) = write_volume(&volumes, i, CONFIG.res_dir.as_str()) {
+                error!("{}: write volume: {:?}", volumes[i].src_file_name, e);
+            }
+        });
+    }
+    pool.join();
+    let books = volumes.iter().map(|v| &v.book).collect::<Vec<&Book>>();
+
+    if CONFIG.export_json {
+        let path = join_file_path(CONFIG.res_dir.as_str(), export::JSONL_FILE);
+        export::write_books_jsonl(&path, &books)?;
+        info!("Write JSON Lines result: Ok, {} volume(s)", books.len());
+    }
+    if CONFIG.epub.collection {
+        let epub_dir = Path::new(CONFIG.res_dir.as_str()).join(epub::EPUB_DIR);
+        epub::write_collection_epub(&epub_dir, &books)?;
+        info!("Write collection EPUB result: Ok, {} volume(s)", books.len());
+    }
+    if !CONFIG.index_template.is_empty() {
+        collection::write_index(Path::new(CONFIG.res_dir.as_str()), &volumes)?;
+        info!("Write index result: Ok, {} volume(s)", volumes.len());
+    }
+    if !CONFIG.lookup_template.is_empty() {
+        collection::write_lookup(Path::new(CONFIG.res_dir.as_str()), &volumes)?;
+        info!("Write lookup result: Ok, {} volume(s)", volumes.len());
+    }
+    if !CONFIG.search_template.is_empty() {
+        search::write_search(Path::new(CONFIG.res_dir.as_str()), &volumes)?;
+        info!("Write search index result: Ok, {} volume(s)", volumes.len());
+    }
+    if !CONFIG.status_template.is_empty() {
+        status::write_status(Path::new(CONFIG.res_dir.as_str()), &volumes)?;
+        info!("Write status result: Ok, {} volume(s)", volumes.len());
+    }
     Ok(())
 }
 
-fn process_file(src_file_path: PathBuf, res_dir_name: &str)->Result<()> {
+/// Чтение и разбор одного исходного файла с записью отчёта
+fn process_file(src_file_path: PathBuf, fix: bool)->Result<Option<Volume>> {
+    let (volume, report) = read_file(src_file_path.clone(), fix)?;
+    for (class, count) in report.unknown_classes() {
+        warn!("{}: skipped {} paragraph(s) of unknown class {}", src_file_path.display(), count, class);
+    }
+
+    // Generate and write report; без отчёта том всё равно выводится
+    match generate_report(&report).and_then(|text| write_report(&src_file_path, text)) {
+        Ok(()) => info!("Write report result: Ok"),
+        Err(e) => error!("{}: write report: {:?}", src_file_path.display(), e),
+    }
+    Ok(volume)
+}
+
+/// Чтение и разбор одного исходного файла: HTML или ранее выгруженный JSON;
+/// с `fix` - безопасные типографские исправления до проверки
+fn read_file(src_file_path: PathBuf, fix: bool)->Result<(Option<Volume>, BookReport)> {
+    let book_num = parse_book_num(path_2_str(&src_file_path)?)?;
+    read_file_as(src_file_path, book_num, fix)
+}
+
+/// То же для тома `book_num`, независимо от имени файла
+fn read_file_as(src_file_path: PathBuf, book_num: u32, fix: bool)->Result<(Option<Volume>, BookReport)> {
     let str = fs::read_to_string(&src_file_path)?;
     let src_file_name = path_2_str(&src_file_path)?;
-    let book_num = parse_book_num(src_file_name)?;
-
-    let (book, report) = parse_book(book_num, str.as_str())?;
-
-    // Generate and write Book
-    if let Some(book) = book {
-        let new_book_text = generate_book(&book)?;
-        info!("{}", join_file_path(res_dir_name, src_file_name).to_str().unwrap());
-        
-        let res = write_book(
-            join_file_path(res_dir_name, src_file_name),
-            new_book_text.as_str(),
-        )?;
-        
-        info!("Write Book result:{:?}", res);
-    }
-
-    // Generate and write report
-    let report_text = generate_report(report)?;
-    let res = write_report(report_text)?;
-    info!("Write report result: {:?}", res);
+
+    let (mut book, mut report) = match src_file_path.extension().and_then(|e| e.to_str()) {
+        Some("json") => load_book(book_num, str.as_str())?,
+        _ => parse_book(book_num, str.as_str())?,
+    };
+    if let (Some(book), true) = (&mut book, fix) {
+        for f in lint::fix_book(book, &CONFIG.lint.disabled) {
+            report.add_fix(&f);
+        }
+    }
+    if let (Some(book), true) = (&book, CONFIG.lint.enabled) {
+        for warning in lint::lint_book(book, &CONFIG.lint.disabled) {
+            report.add_warning(&warning);
+        }
+    }
+    let volume = book.map(|book| Volume { problems: report.problems(), ..Volume::new(src_file_name, book) });
+    Ok((volume, report))
+}
+
+/// Том `book_num` из одного файла с любым именем; файл без стихотворений - пустой том
+fn read_book(src_file_path: PathBuf, book_num: u32) -> Result<Book> {
+    Ok(read_file_as(src_file_path, book_num, false)?.0.map_or_else(|| Book::new(book_num), |v| v.book))
+}
+
+/// Номер тома для `diff`: из имени первого файла, где он есть ("Vol. 07.html" и "draft.html" -> 7);
+/// если его нет ни в одном имени - 0
+fn diff_book_num(old: &str, new: &str) -> u32 {
+    [old, new].iter()
+        .find_map(|f| path_2_str(Path::new(f)).ok().and_then(|name| parse_book_num(name).ok()))
+        .unwrap_or_default()
+}
+
+/// Generate and write Book: все выходы тома `volumes[i]`
+fn write_volume(volumes: &[Volume], i: usize, res_dir_name: &str) -> Result<()> {
+    let volume = &volumes[i];
+    let book = &volume.book;
+    let src_file_name = volume.src_file_name.as_str();
+    if CONFIG.export_json {
+        let json_dir = Path::new(res_dir_name).join(export::JSON_DIR);
+        export::write_book_json(&json_dir, src_file_name, book)?;
+    }
+    if CONFIG.epub.enabled {
+        let epub_dir = Path::new(res_dir_name).join(epub::EPUB_DIR);
+        epub::write_book_epub(&epub_dir, src_file_name, book)?;
+    }
+
+    for output in CONFIG.outputs() {
+        let renderer = render::output_renderer(&output)?;
+        let pages = volume.pages(&output)?;
+        let mut nav = Navigation::new(volumes, i, &output)?;
+        for (page, (link, numbers)) in pages.iter().enumerate() {
+            let new_book_text = if pages.len() > 1 {
+                nav.page = Some(Pagination::new(&pages, page));
+                renderer.render(&volume.page_book(numbers), &nav)?
+            } else {
+                renderer.render(book, &nav)?
+            };
+            let res_file_path = join_file_path(res_dir_name, link);
+            info!("{}: {}", output.name, res_file_path.to_str().unwrap());
+
+            write_book(res_file_path, new_book_text.as_str())?;
+
+            info!("Write Book result: Ok");
+        }
+    }
     Ok(())
 }
 
-fn generate_report(report: BookReport) -> Result<String> {
-    todo!()
+/// Отчёт о разборе тома по шаблону `problem_template`
+fn generate_report(report: &BookReport) -> Result<String> {
+    let text = template::TEMPLATES.render(CONFIG.problem_template.as_str(), &report::report_context(report))?;
+    Ok(text)
 }
 
-fn write_report(report_text: String) -> Result<()> {
-    todo!()
+/// "Vol. 07.html" -> "<res_dir>/reports/Vol. 07.html"
+fn write_report(src_file_path: &Path, report_text: String) -> Result<()> {
+    let src_file_name = path_2_str(src_file_path)?;
+    let stem = Path::new(src_file_name).file_stem().and_then(|s| s.to_str()).unwrap_or(src_file_name);
+    let res_file_path = Path::new(CONFIG.res_dir.as_str()).join(report::REPORT_DIR).join(format!("{}.html", stem));
+    write_book(res_file_path, report_text.as_str())
 }
 
 fn write_book(path: PathBuf, book_text: &str) -> Result<()> {
-    let file = fs::OpenOptions::new().create(true).write(true).open(path)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = fs::OpenOptions::new().create(true).write(true).truncate(true).open(path)?;
     let mut file = BufWriter::new(file);
     file.write_all(book_text.as_bytes()).unwrap();
     file.flush()?;
     Ok(())
 }
 
-fn generate_book(book: &Book) -> Result<String> {
-    let mut context = Context::new();
-    context.insert("book", book);
-    context.insert("books", &book.get_ordered_poems());
-    // let res = TEMPLATES.render(CONFIG.poem_template.as_str(), &context);
-    TEMPLATES.render(CONFIG.poem_template.as_str(), &context).map_err(Error::from)
-    // res.unwrap()
-}
-
 fn prepare_res_dir(dir_name: &str) -> Result<()> {
-    fs::remove_dir_all(dir_name).unwrap();   // Если не удалось удалить - нестрашно
+    let _ = fs::remove_dir_all(dir_name);   // Если не удалось удалить - нестрашно
     fs::create_dir_all(dir_name)?;  // Не удалось создать - ошибка
     Ok(())
 }
 
 fn parse_book(book_num: u32, html_text: &str) -> Result<(Option<Book>, BookReport)> {
+    parse_book_with(book_num, html_text, CONFIG.unknown_class_errors)
+}
+
+/// Разбор тома; `unknown_class_errors` - см. одноимённый параметр конфигурации
+fn parse_book_with(book_num: u32, html_text: &str, unknown_class_errors: bool) -> Result<(Option<Book>, BookReport)> {
     let mut builder = BookBuilder::new(book_num);
     let document = Html::parse_document(html_text);
     let selector = Selector::parse("body div")
@@ -120,7 +322,7 @@ fn parse_book(book_num: u32, html_text: &str) -> Result<(Option<Book>, BookRepor
             error
         })?;
     let select = document.select(&selector);
-    for q in select.filter(|x| { x.children().count() > 100 }).into_iter() {
+    for q in select.filter(|x| { x.children().count() > 100 }) {
         for p in q.child_elements() {
             // let cls = p.attr("class").ok_or(Error::Html{html:p.html()})?;
             let cls = p.attr("class").ok_or_else(|| Error::Html{html:p.html()})?;
@@ -130,21 +332,51 @@ fn parse_book(book_num: u32, html_text: &str) -> Result<(Option<Book>, BookRepor
             // };
             match cls {
                 "_7_number" | "_7_number-long" => {
-                    let num = builder.parse_poem_num(&p.inner_html());
-                    builder.proc_number(num);
-                    // println!("{}", num)
+                    builder.proc_number_text(&utils::line_text(&p, &[]));
                 }
                 "_7_poem" => {
-                    let line = p.inner_html();
-                    let line = utils::re_remove_tags(&line);
+                    let line = utils::line_text(&p, &CONFIG.rich_text_tags);
                     builder.proc_line(line);
                     // println!("{}", p.inner_html())
                 }
-                &_ => {}
+                cls if CONFIG.language_classes.contains_key(cls) => {
+                    let line = utils::line_text(&p, &CONFIG.rich_text_tags);
+                    builder.proc_lang_line(&CONFIG.language_classes[cls], line);
+                }
+                cls if CONFIG.block_classes.contains_key(cls) => {
+                    let line = utils::line_text(&p, &CONFIG.rich_text_tags);
+                    builder.proc_block(CONFIG.block_classes[cls], line);
+                }
+                cls => builder.proc_unknown_class(cls),
             }
             // println!("{}", p.html())
         }
     }
+    let (book, mut report) = builder.build();
+    if unknown_class_errors {
+        report.add_unknown_class_errors();
+    }
+    Ok((book, report))
+}
+
+/// Загрузка ранее выгруженного в JSON тома и проверка его той же логикой, что и при разборе HTML
+fn load_book(book_num: u32, json_text: &str) -> Result<(Option<Book>, BookReport)> {
+    let src: Book = serde_json::from_str(json_text)?;
+    let mut builder = BookBuilder::new(book_num);
+    if src.nn != book_num {
+        builder.add_error(&Error::VolumeNumberMismatch{expected: book_num, found: src.nn});
+    }
+    builder.proc_blocks(src.blocks);
+    for (key, poem) in src.poems.into_iter().chain(src.incomplete) {
+        if key != poem.nn {
+            builder.add_error(&Error::PoemNumberMismatch{key, number: poem.nn});
+        }
+        let mut titles = poem.titles;
+        for (lang, lines) in poem.text {
+            let title = titles.swap_remove(&lang);
+            builder.proc_section(poem.nn, lang, title, lines);
+        }
+    }
     Ok(builder.build())
 }
 
@@ -160,7 +392,7 @@ mod tests {
         let file_name = format!("Vol. {:02}.html", n);
         let src_file_path = Path::new(CONFIG.src_dir.as_str()).join(file_name);
 
-        let src_file_name = src_file_path.file_name().map(|s|s.to_str()).flatten()
+        let src_file_name = src_file_path.file_name().and_then(|s|s.to_str())
         .ok_or_else(|| 
             Error::PathError{
                 path: src_file_path.as_os_str().to_string_lossy().into()
@@ -173,14 +405,91 @@ mod tests {
         let n = 3;
         let file_name = format!("Vol. {:02}.html", n);
         let src = Path::new(CONFIG.src_dir.as_str()).join(file_name);
-        let res = process_file(src, CONFIG.res_dir.as_str());
+        let res = process_file(src, false);
         info!("===> {res:?}");
         Ok(())
     }
 
+    #[test]
+    fn test_load_book() -> Result<()> {
+        let json = r#"{"nn": 7, "poems": {
+            "1": {"nn": 1, "nn_str": "", "text": {"en": ["Qwerty"], "ru": ["Йцукен"]}},
+            "2": {"nn": 3, "nn_str": "3", "text": {"en": ["Qwerty"]}}
+        }}"#;
+        let (book, report) = load_book(7, json)?;
+        let book = book.unwrap();
+        assert_eq!(1, book.poems.len());
+        assert_eq!("1", book.poems[&1].nn_str);
+        assert_eq!(2, report.errors().len());
+        assert!(report.errors()[0].starts_with("PoemNumberMismatch"));
+        assert!(report.errors()[1].starts_with("NoTranslationForPoem"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_hostile_corpus() -> Result<()> {
+        let (book, report) = parse_book(99, include_str!("../testdata/hostile.html"))?;
+        let book = book.unwrap();
+        assert_eq!(40, book.poems.len(), "{:?}", report);
+        assert!(report.unknown_classes().is_empty());
+        assert_eq!("<img src=x onerror=alert(1)>", book.poems[&99003].lines("en")[0]);
+        assert_eq!("Innocent line", book.poems[&99005].lines("en")[0]);
+        assert_eq!("bold and italic", book.poems[&99005].lines("en")[1]);
+
+        let html = render::renderer("html", CONFIG.poem_template.as_str())?
+            .render(&book, &Navigation::default())?;
+        for raw in ["<img", "<script", "onclick", "onmouseover", "<h1>Injected", "<b>", "<i>"] {
+            assert!(!html.contains(raw), "{} in output", raw);
+        }
+        assert!(html.contains("&lt;img src=x onerror=alert(1)&gt;"));
+        assert!(html.contains("Tom &amp; Jerry say &quot;hi&quot; &amp;amp; &#x27;bye&#x27;"));
+        assert!(html.contains("{{ book.nn }} {% raw %} not a template"));
+        assert!(html.contains("A\u{a0}&lt;\u{a0}B &gt; C"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrapped_number() -> Result<()> {
+        let mut html = String::from("<html><body><div>");
+        for (lang_line, nn_suffix) in [("Qwerty", "<br/>(Dedication)"), ("Йцукен", "")] {
+            for nn in 1..=60 {
+                let suffix = if nn == 5 { nn_suffix } else { "" };
+                html += &format!(r#"<p class="_7_number">00,{:03}.{}</p><p class="_7_poem">{} {}</p>"#, nn, suffix, lang_line, nn);
+            }
+        }
+        html += "</div></body></html>";
+        let (book, report) = parse_book(7, &html)?;
+        let book = book.unwrap();
+        assert!(report.errors().is_empty(), "{:?}", report.errors());
+        assert_eq!(60, book.poems.len());
+        assert_eq!("(Dedication)", book.poems[&5].titles["en"]);
+        assert_eq!(vec!["Qwerty 5"], book.poems[&5].lines("en"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_classes() -> Result<()> {
+        let mut html = String::from("<html><body><div>");
+        for nn in 1..=60 {
+            html += &format!(r#"<p class="_7_number">{}</p><p class="_7_poem-first">Qwerty {}</p>"#, nn, nn);
+        }
+        html += "</div></body></html>";
+        let (book, report) = parse_book_with(7, &html, false)?;
+        assert!(book.unwrap().poems.is_empty());
+        assert_eq!(60, report.unknown_classes()["_7_poem-first"]);
+        assert!(!report.errors().iter().any(|e| e.starts_with("UnknownClass")));
+
+        let (_, report) = parse_book_with(7, &html, true)?;
+        assert!(report.errors().iter().any(|e| e == r#"UnknownClass { class: "_7_poem-first", count: 60 }"#));
+        Ok(())
+    }
+
     #[test]
     fn test_book_num() -> Result<()> {
         assert_eq!(7, parse_book_num("Vol. 07.html")?);
+        assert_eq!(7, diff_book_num("old/Vol. 07.html", "draft.html"));
+        assert_eq!(7, diff_book_num("draft.html", "new/Vol. 07.json"));
+        assert_eq!(0, diff_book_num("a.html", "b.html"));
         Ok(())
     }
 }
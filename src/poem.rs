@@ -1,24 +1,38 @@
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Poem {
     pub nn: u32,
     pub nn_str: String,
-    pub en: Vec<String>,
-    pub ru: Vec<String>,
+    /// Код языка -> строки стихотворения, в порядке следования языков
+    pub text: IndexMap<String, Vec<String>>,
+    /// Код языка -> название из абзаца номера ("01,234. (Посвящение)"), если оно есть
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub titles: IndexMap<String, String>,
 }
 
 impl Poem {
-    pub fn new(nn: u32, en: Vec<String>, ru: Vec<String>) -> Self {
+    pub fn new(nn: u32, text: IndexMap<String, Vec<String>>) -> Self {
         Self {
             nn,
             nn_str: Self::nn_str(nn, " "),
-            en,
-            ru,
+            text,
+            titles: Default::default(),
         }
     }
 
-    fn nn_str(nn: u32, sep: &str) -> String {
+    /// Строки стихотворения на языке `lang` (пусто, если перевода нет)
+    pub fn lines(&self, lang: &str) -> &[String] {
+        self.text.get(lang).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn languages(&self) -> impl Iterator<Item = &str> {
+        self.text.keys().map(String::as_str)
+    }
+
+    /// 1234567 -> "1 234 567" для `sep` = " "
+    pub fn nn_str(nn: u32, sep: &str) -> String {
         // let nn = match self {
         //     Poem::En { nn,.. } => nn,
         //     Poem::EnRu { nn, .. } => nn,
@@ -42,14 +56,18 @@ pub mod tests {
     pub fn get_test_poem(nn: u32) -> Poem {
         Poem::new(
             nn,
-            vec![String::from(format!("Qwerty {}-1", nn)),
-                 String::from(format!("Qwerty {}-2", nn)),
-                 String::from(format!("Qwerty {}-3", nn)),
-            ],
-            vec![String::from(format!("Йцукен {}-1", nn)),
-                 String::from(format!("Йцукен {}-2", nn)),
-                 String::from(format!("Йцукен {}-3", nn)),
-            ],
+            IndexMap::from([
+                ("en".to_string(),
+                 vec![format!("Qwerty {}-1", nn),
+                      format!("Qwerty {}-2", nn),
+                      format!("Qwerty {}-3", nn),
+                 ]),
+                ("ru".to_string(),
+                 vec![format!("Йцукен {}-1", nn),
+                      format!("Йцукен {}-2", nn),
+                      format!("Йцукен {}-3", nn),
+                 ]),
+            ]),
         )
     }
 
@@ -63,4 +81,12 @@ pub mod tests {
         let p = get_test_poem(13_234_567);
         println!("Print {}", p.nn_str);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_lines() {
+        let p = get_test_poem(5);
+        assert_eq!(vec!["en", "ru"], p.languages().collect::<Vec<_>>());
+        assert_eq!("Йцукен 5-2", p.lines("ru")[1]);
+        assert!(p.lines("uk").is_empty());
+    }
+}
@@ -0,0 +1,277 @@
+use tera::Context;
+
+use crate::book::{Block, BlockKind, Book};
+use crate::collection::Navigation;
+use crate::config::{Output, CONFIG};
+use crate::locale;
+use crate::template::TEMPLATES;
+use crate::utils::plain_text;
+use crate::{Error, Result};
+
+/// Представление тома в одном из выходных форматов
+pub trait Renderer: Send + Sync {
+    /// Расширение выходного файла, без точки
+    fn extension(&self) -> &str;
+
+    fn render(&self, book: &Book, nav: &Navigation) -> Result<String>;
+}
+
+/// Формат выхода; для "html" используется шаблон выхода или `poem_template`
+pub fn output_renderer(output: &Output) -> Result<Box<dyn Renderer>> {
+    let template = output.template.as_deref().unwrap_or(CONFIG.poem_template.as_str());
+    renderer(output.renderer.as_str(), template)
+}
+
+/// Формат по имени: "html" (через шаблон `template`), "text" или "markdown"
+pub fn renderer(format: &str, template: &str) -> Result<Box<dyn Renderer>> {
+    match format {
+        "html" => Ok(Box::new(HtmlRenderer::new(template))),
+        "text" | "txt" => Ok(Box::new(TextRenderer)),
+        "markdown" | "md" => Ok(Box::new(MarkdownRenderer)),
+        _ => Err(Error::UnknownFormat { format: format.to_string() }),
+    }
+}
+
+/// HTML через шаблон Tera
+pub struct HtmlRenderer {
+    template: String,
+}
+
+impl HtmlRenderer {
+    pub fn new(template: &str) -> Self {
+        Self { template: template.to_string() }
+    }
+}
+
+impl Renderer for HtmlRenderer {
+    fn extension(&self) -> &str {
+        "html"
+    }
+
+    fn render(&self, book: &Book, nav: &Navigation) -> Result<String> {
+        TEMPLATES.render(self.template.as_str(), &book_context(book, nav)).map_err(Error::from)
+    }
+}
+
+/// Контекст шаблона тома
+pub fn book_context(book: &Book, nav: &Navigation) -> Context {
+    let mut context = locale::context();
+    context.insert("book", book);
+    context.insert("books", &book.get_ordered_poems());
+    insert_blocks(&mut context, book);
+    context.insert("languages", &CONFIG.languages);
+    context.insert("prev_volume", &nav.prev);
+    context.insert("next_volume", &nav.next);
+    context.insert("page", &nav.page);
+    context
+}
+
+/// Блоки тома для шаблона: `blocks` и `blocks_after` по номеру стихотворения, `end_blocks`
+pub fn insert_blocks(context: &mut Context, book: &Book) {
+    let placed = book.blocks_by_poem();
+    context.insert("blocks", &placed.before);
+    context.insert("blocks_after", &placed.after);
+    context.insert("end_blocks", &placed.end);
+}
+
+/// Простой текст: номер, затем строки на каждом языке, блоки разделены пустой строкой
+pub struct TextRenderer;
+
+impl Renderer for TextRenderer {
+    fn extension(&self) -> &str {
+        "txt"
+    }
+
+    fn render(&self, book: &Book, _nav: &Navigation) -> Result<String> {
+        let mut res = format!("{} {}\n", locale::msg("volume"), book.nn);
+        let placed = book.blocks_by_poem();
+        let push_blocks = |res: &mut String, blocks: &[&Block]| {
+            for block in blocks {
+                res.push('\n');
+                for line in &block.lines {
+                    res.push_str(&plain_text(line));
+                    res.push('\n');
+                }
+            }
+        };
+        for poem in book.get_ordered_poems() {
+            push_blocks(&mut res, placed.before.get(&poem.nn.to_string()).map_or(&[], Vec::as_slice));
+            res.push('\n');
+            res.push_str(&poem.nn_str);
+            res.push('\n');
+            for lang in &CONFIG.languages {
+                res.push('\n');
+                if let Some(title) = poem.titles.get(lang) {
+                    res.push_str(title);
+                    res.push('\n');
+                }
+                for line in poem.lines(lang) {
+                    res.push_str(&plain_text(line));
+                    res.push('\n');
+                }
+            }
+            push_blocks(&mut res, placed.after.get(&poem.nn.to_string()).map_or(&[], Vec::as_slice));
+        }
+        push_blocks(&mut res, &placed.end);
+        Ok(res)
+    }
+}
+
+/// Markdown: номер стихотворения заголовком, строки с жёстким переносом
+pub struct MarkdownRenderer;
+
+impl Renderer for MarkdownRenderer {
+    fn extension(&self) -> &str {
+        "md"
+    }
+
+    fn render(&self, book: &Book, _nav: &Navigation) -> Result<String> {
+        let mut res = format!("# {} {}\n", locale::msg("volume"), book.nn);
+        let placed = book.blocks_by_poem();
+        let push_blocks = |res: &mut String, blocks: &[&Block]| {
+            for block in blocks {
+                let lines = block.lines.iter().map(|l| md_escape(&plain_text(l))).collect::<Vec<_>>();
+                match block.kind {
+                    BlockKind::SectionHeading => res.push_str(&format!("\n## {}\n", lines.join(" "))),
+                    BlockKind::Footnote => res.push_str(&format!("\n> {}\n", lines.join("\\\n> "))),
+                    BlockKind::FrontMatter => res.push_str(&format!("\n{}\n", lines.join("\\\n"))),
+                }
+            }
+        };
+        for poem in book.get_ordered_poems() {
+            push_blocks(&mut res, placed.before.get(&poem.nn.to_string()).map_or(&[], Vec::as_slice));
+            res.push_str(&format!("\n## {}\n", poem.nn_str));
+            for lang in &CONFIG.languages {
+                let lines = poem.lines(lang);
+                if lines.is_empty() {
+                    continue;
+                }
+                res.push('\n');
+                if let Some(title) = poem.titles.get(lang) {
+                    res.push_str(&format!("*{}*\n\n", md_escape(title)));
+                }
+                let lines = lines.iter().map(|l| md_escape(&plain_text(l))).collect::<Vec<_>>();
+                res.push_str(&lines.join("\\\n"));
+                res.push('\n');
+            }
+            push_blocks(&mut res, placed.after.get(&poem.nn.to_string()).map_or(&[], Vec::as_slice));
+        }
+        push_blocks(&mut res, &placed.end);
+        Ok(res)
+    }
+}
+
+/// Экранирование, чтобы строка стихотворения не стала разметкой
+fn md_escape(line: &str) -> String {
+    let mut res = String::with_capacity(line.len());
+    for c in line.trim().chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>' | '#' | '|') {
+            res.push('\\');
+        }
+        res.push(c);
+    }
+    // "- ", "+ ", "1. " в начале строки превратились бы в список
+    if res.starts_with("- ") || res.starts_with("+ ") {
+        res.insert(0, '\\');
+    }
+    let digits = res.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0 && res[digits..].starts_with(['.', ')']) {
+        res.insert(digits, '\\');
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::book::tests::get_test_book;
+    use crate::collection::{Pagination, Volume};
+    use crate::poem::tests::get_test_poem;
+
+    use super::*;
+
+    #[test]
+    fn test_text() -> Result<()> {
+        let text = renderer("text", "")?.render(&get_test_book(), &Navigation::default())?;
+        assert!(text.starts_with("Часть 7\n\n1\n\nQwerty 1-1\nQwerty 1-2\nQwerty 1-3\n\nЙцукен 1-1\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown() -> Result<()> {
+        let text = renderer("markdown", "")?.render(&get_test_book(), &Navigation::default())?;
+        assert!(text.contains("\n## 2\n\nQwerty 2-1\\\nQwerty 2-2\\\nQwerty 2-3\n\nЙцукен 2-1\\\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocks_and_titles() -> Result<()> {
+        let mut book = get_test_book();
+        book.poems.get_mut(&3).unwrap().titles.insert("ru".to_string(), "(Посвящение)".to_string());
+        let block = |kind, before, line: &str| Block { kind, lang: None, before, after: None, lines: vec![line.to_string()] };
+        book.blocks.push(block(BlockKind::SectionHeading, Some(2), "Part II"));
+        book.blocks.push(block(BlockKind::Footnote, None, "* Note"));
+        book.blocks.push(Block { after: Some(4), ..block(BlockKind::Footnote, None, "* Note 4") });
+        // Номер, который не стал стихотворением: блок переходит к следующему
+        book.blocks.push(block(BlockKind::SectionHeading, Some(99), "Part X"));
+        book.add_incomplete(get_test_poem(100));
+
+        let text = renderer("text", "")?.render(&book, &Navigation::default())?;
+        assert!(text.contains("Йцукен 1-3\n\nPart II\n\n2\n"));
+        assert!(text.ends_with("\n\n* Note\n"));
+        assert!(text.contains("Йцукен 4-3\n\n* Note 4\n\n5\n"));
+        assert!(text.contains("\n\nPart X\n\n100\n"));
+        assert!(text.contains("Qwerty 3-3\n\n(Посвящение)\nЙцукен 3-1\n"));
+
+        let text = renderer("markdown", "")?.render(&book, &Navigation::default())?;
+        assert!(text.contains("\n## Part II\n\n## 2\n"));
+        assert!(text.ends_with("\n> \\* Note\n"));
+        assert!(text.contains("\n## 3\n\nQwerty 3-1\\\nQwerty 3-2\\\nQwerty 3-3\n\n*(Посвящение)*\n\nЙцукен 3-1"));
+
+        let text = renderer("html", CONFIG.poem_template.as_str())?.render(&book, &Navigation::default())?;
+        let heading = text.find(r#"<div class="section_heading">Part II</div>"#).unwrap();
+        assert!(text.find(r#"id="1""#).unwrap() < heading && heading < text.find(r#"id="2""#).unwrap());
+        assert!(text.contains(r#"<div class="footnote">* Note</div>"#));
+        let note = text.find("* Note 4").unwrap();
+        assert!(text.find(r#"id="4""#).unwrap() < note && note < text.find(r#"id="5""#).unwrap());
+        let part = text.find("Part X").unwrap();
+        assert!(text.find(r#"id="7""#).unwrap() < part && part < text.find(r#"id="100""#).unwrap());
+        assert!(text.contains(r#"<span class="title">(Посвящение)</span><br>"#));
+        assert_eq!(1, text.matches(r#"class="title""#).count());
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_anchors() -> Result<()> {
+        let volume = Volume::new("Vol. 07.html", get_test_book());
+        let output: Output = toml::from_str("name = 'web'\nfile_name = 'web/{volume:02}.html'\npage_size = 3").unwrap();
+        let pages = volume.pages(&output)?;
+        let html = renderer("html", CONFIG.poem_template.as_str())?;
+        let render_page = |i: usize| {
+            let nav = Navigation { page: Some(Pagination::new(&pages, i)), ..Default::default() };
+            html.render(&volume.page_book(&pages[i].1), &nav)
+        };
+
+        // "07.html#7" на первой странице ведёт на страницу со стихотворением 7
+        let first = render_page(0)?;
+        assert!(first.contains(r#"<a id="7" href="07-3.html#7">7</a>"#));
+        assert!(!first.contains(r#"<a id="2" "#));
+        let (i, _) = pages.iter().enumerate().find(|(_, (link, _))| link == "web/07-3.html").unwrap();
+        let last = render_page(i)?;
+        assert!(last.contains(r#"<div id="7" class="aa">"#));
+        assert!(last.contains(r#"href="07-3.html#7""#));
+        assert!(!last.contains(r#"class="anchors""#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_md_escape() {
+        assert_eq!("\\- one \\*two\\*", md_escape("- one *two*"));
+        assert_eq!("1999\\. Year", md_escape("1999. Year"));
+        assert_eq!("1 000 years", md_escape("1 000 years"));
+    }
+
+    #[test]
+    fn test_unknown_format() {
+        assert!(renderer("pdf", "").is_err());
+    }
+}
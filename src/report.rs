@@ -1,19 +1,37 @@
-// use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-use std::fmt::format;
+use serde::Serialize;
+use tera::Context;
 
-use crate::{Result, Error};
+use crate::lint::{Fix, Warning};
+use crate::{locale, Error};
 
-#[derive(Debug)]
+/// Каталог отчётов внутри `res_dir`
+pub static REPORT_DIR: &str = "reports";
+
+#[derive(Debug, Serialize)]
 pub struct BookReport{
     nn: u32,
-    errors: Vec<String>
+    errors: Vec<String>,
+    /// Замечания линтера: "RU001 6001 ru:2: ..."
+    warnings: Vec<String>,
+    /// Исправления `build --fix`: "RU001 6001 ru:2: было -> стало"
+    fixes: Vec<String>,
+    /// Пропущенные при разборе классы абзацев -> число абзацев
+    unknown_classes: BTreeMap<String, usize>,
+    /// Ошибки, кроме `NoTranslationForPoem`: неполные стихотворения считаются отдельно
+    #[serde(skip)]
+    problems: usize,
 }
 impl BookReport {
     pub(crate) fn new(nn: u32) -> Self {
         Self { 
             nn,
             errors: Default::default(),
+            warnings: Default::default(),
+            fixes: Default::default(),
+            unknown_classes: Default::default(),
+            problems: 0,
          }
     }
     
@@ -22,6 +40,76 @@ impl BookReport {
     // }
 
     pub fn add(&mut self, error: &Error){
+        if !matches!(error, Error::NoTranslationForPoem { .. }) {
+            self.problems += 1;
+        }
         self.errors.push(format!("{:?}", error));
     }
-}
\ No newline at end of file
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    /// Число ошибок для хода перевода, без неполных стихотворений
+    pub fn problems(&self) -> usize {
+        self.problems
+    }
+
+    pub fn add_warning(&mut self, warning: &Warning) {
+        self.warnings.push(warning.to_string());
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn add_fix(&mut self, fix: &Fix) {
+        self.fixes.push(fix.to_string());
+    }
+
+    pub fn add_unknown_class(&mut self, class: &str) {
+        *self.unknown_classes.entry(class.to_string()).or_default() += 1;
+    }
+
+    pub fn unknown_classes(&self) -> &BTreeMap<String, usize> {
+        &self.unknown_classes
+    }
+
+    /// Каждый пропущенный класс - ошибка `UnknownClass`
+    pub fn add_unknown_class_errors(&mut self) {
+        let errors = self.unknown_classes.iter()
+            .map(|(class, &count)| Error::UnknownClass { class: class.clone(), count })
+            .collect::<Vec<_>>();
+        for error in &errors {
+            self.add(error);
+        }
+    }
+}
+
+/// Контекст шаблона отчёта `problem_template`
+pub fn report_context(report: &BookReport) -> Context {
+    let mut context = locale::context();
+    context.insert("report", report);
+    context
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_classes() {
+        let mut report = BookReport::new(7);
+        report.add_unknown_class("_7_poem-first");
+        report.add_unknown_class("_7_poem-first");
+        report.add_unknown_class("_7_poem-last");
+        assert_eq!(2, report.unknown_classes()["_7_poem-first"]);
+        assert!(report.errors().is_empty());
+
+        report.add_unknown_class_errors();
+        assert_eq!(vec![
+            r#"UnknownClass { class: "_7_poem-first", count: 2 }"#,
+            r#"UnknownClass { class: "_7_poem-last", count: 1 }"#,
+        ], report.errors());
+    }
+}
@@ -0,0 +1,83 @@
+use crate::config::CONFIG;
+use crate::utils::plain_text;
+
+/// Письменность текста
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Script {
+    Latin,
+    Cyrillic,
+}
+
+impl Script {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "latin" => Some(Self::Latin),
+            "cyrillic" => Some(Self::Cyrillic),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Latin => "latin",
+            Self::Cyrillic => "cyrillic",
+        }
+    }
+
+    fn of(c: char) -> Option<Self> {
+        match c {
+            'a'..='z' | 'A'..='Z' | '\u{00C0}'..='\u{024F}' if c.is_alphabetic() => Some(Self::Latin),
+            '\u{0400}'..='\u{04FF}' if c.is_alphabetic() => Some(Self::Cyrillic),
+            _ => None,
+        }
+    }
+}
+
+/// Письменность языка: из `language_scripts`, иначе для известных кодов
+pub fn language_script(lang: &str) -> Option<Script> {
+    if let Some(name) = CONFIG.language_scripts.get(lang) {
+        return Script::parse(name);
+    }
+    match lang {
+        "en" | "de" | "fr" | "es" | "it" | "pt" | "nl" | "pl" | "cs" => Some(Script::Latin),
+        "ru" | "uk" | "be" | "bg" | "sr" | "mk" | "kk" => Some(Script::Cyrillic),
+        _ => None,
+    }
+}
+
+/// Письменность большей части букв в строках; `None` - букв нет или поровну
+pub fn detect(lines: &[String]) -> Option<Script> {
+    let (mut latin, mut cyrillic) = (0usize, 0usize);
+    for c in lines.iter().flat_map(|l| plain_text(l).chars().collect::<Vec<_>>()) {
+        match Script::of(c) {
+            Some(Script::Latin) => latin += 1,
+            Some(Script::Cyrillic) => cyrillic += 1,
+            None => {}
+        }
+    }
+    match latin.cmp(&cyrillic) {
+        std::cmp::Ordering::Greater => Some(Script::Latin),
+        std::cmp::Ordering::Less => Some(Script::Cyrillic),
+        std::cmp::Ordering::Equal => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('|').map(String::from).collect()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Some(Script::Latin), detect(&lines("Tree of service|Light")));
+        assert_eq!(Some(Script::Cyrillic), detect(&lines("Дерево служения, Sri Chinmoy")));
+        assert_eq!(Some(Script::Latin), detect(&lines("Café über")));
+        assert_eq!(None, detect(&lines("6001 — !")));
+        assert_eq!(None, detect(&[]));
+        assert_eq!(Some(Script::Cyrillic), language_script("ru"));
+        assert_eq!(None, language_script("zz"));
+    }
+}
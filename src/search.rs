@@ -0,0 +1,210 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use regex::Regex;
+use serde::Serialize;
+use tera::Context;
+
+use crate::cli::{self, SearchArgs, SearchMode};
+use crate::collection::Volume;
+use crate::config::CONFIG;
+use crate::locale;
+use crate::poem::Poem;
+use crate::template::TEMPLATES;
+use crate::utils::plain_text;
+use crate::{Error, Result};
+
+/// Имя файла индекса внутри `res_dir`
+pub static INDEX_FILE: &str = "search_index.js";
+
+/// Слово в форме для поиска: строчные буквы, "ё" -> "е"
+pub fn normalize_word(word: &str) -> String {
+    word.chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| if c == 'ё' { 'е' } else { c })
+        .collect()
+}
+
+/// Слова строки в форме для поиска; всё, кроме букв и цифр, - разделитель
+pub fn words(line: &str) -> impl Iterator<Item = String> + '_ {
+    line.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(normalize_word)
+}
+
+/// Слова строки стихотворения, без тегов форматирования
+fn line_words(line: &str) -> Vec<String> {
+    words(&plain_text(line)).collect()
+}
+
+/// Обратный индекс по всем томам, отдельно для каждого языка
+#[derive(Serialize, Debug, Default)]
+pub struct SearchIndex {
+    pub languages: Vec<String>,
+    /// Номер тома -> файл тома относительно `res_dir`
+    pub volumes: BTreeMap<u32, String>,
+    /// Номер стихотворения -> номер тома
+    pub poems: BTreeMap<u32, u32>,
+    /// Номер стихотворения -> файл (страница) тома относительно `res_dir`
+    pub links: BTreeMap<u32, String>,
+    /// Язык -> слово -> номера стихотворений
+    pub words: BTreeMap<String, BTreeMap<String, BTreeSet<u32>>>,
+}
+
+impl SearchIndex {
+    pub fn new(volumes: &[Volume]) -> Result<Self> {
+        let mut res = Self { languages: CONFIG.languages.clone(), ..Default::default() };
+        for volume in volumes {
+            res.volumes.insert(volume.book.nn, volume.link()?);
+            res.links.extend(volume.poem_links()?);
+            for poem in volume.book.get_ordered_poems() {
+                res.poems.insert(poem.nn, volume.book.nn);
+                for (lang, lines) in &poem.text {
+                    let words = res.words.entry(lang.clone()).or_default();
+                    for word in lines.iter().flat_map(|l| line_words(l)) {
+                        words.entry(word).or_default().insert(poem.nn);
+                    }
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// "<res_dir>/search_index.js" и страница поиска `search_template` -> "<res_dir>/search.html".
+/// Индекс - скрипт, а не JSON, чтобы страница работала и при открытии с диска
+pub fn write_search(res_dir: &Path, volumes: &[Volume]) -> Result<()> {
+    let index = SearchIndex::new(volumes)?;
+    let script = format!("var SEARCH_INDEX = {};\n", serde_json::to_string(&index)?);
+    std::fs::write(res_dir.join(INDEX_FILE), script)?;
+
+    let text = TEMPLATES.render(CONFIG.search_template.as_str(), &page_context())?;
+    std::fs::write(res_dir.join("search.html"), text)?;
+    Ok(())
+}
+
+/// Контекст `search_template`
+pub fn page_context() -> Context {
+    let mut context = locale::context();
+    context.insert("index_file", INDEX_FILE);
+    context.insert("languages", &CONFIG.languages);
+    context
+}
+
+/// Запрос команды `search`
+pub enum Query {
+    /// Все слова, в любом порядке
+    Words(BTreeSet<String>),
+    /// Слова подряд, в том числе через перенос строки
+    Phrase(Vec<String>),
+    /// Регулярное выражение для отдельной строки
+    Regex(Regex),
+}
+
+impl Query {
+    /// Запрос без слов ("—", "...") подходил бы к любому стихотворению - это ошибка использования
+    pub fn new(text: &str, mode: SearchMode) -> Result<Self> {
+        if mode != SearchMode::Regex && words(text).next().is_none() {
+            return Err(Error::Usage(format!("No words to search for in {:?}\n\n{}", text, cli::USAGE)));
+        }
+        Ok(match mode {
+            SearchMode::Words => Self::Words(words(text).collect()),
+            SearchMode::Phrase => Self::Phrase(words(text).collect()),
+            SearchMode::Regex => Self::Regex(Regex::new(text)?),
+        })
+    }
+
+    /// Подходят ли строки одного языка стихотворения
+    pub fn matches(&self, lines: &[String]) -> bool {
+        match self {
+            Self::Words(query) => {
+                let found = lines.iter().flat_map(|l| line_words(l)).collect::<BTreeSet<_>>();
+                query.is_subset(&found)
+            }
+            Self::Phrase(query) => {
+                let found = lines.iter().flat_map(|l| line_words(l)).collect::<Vec<_>>();
+                !query.is_empty() && found.windows(query.len()).any(|w| w == query.as_slice())
+            }
+            Self::Regex(re) => lines.iter().any(|l| re.is_match(&plain_text(l))),
+        }
+    }
+
+    /// Подходит ли стихотворение хотя бы на одном из языков `langs` (пусто - на любом)
+    pub fn matches_poem(&self, poem: &Poem, langs: &[String]) -> bool {
+        poem.text.iter()
+            .filter(|(lang, _)| langs.is_empty() || langs.contains(lang))
+            .any(|(_, lines)| self.matches(lines))
+    }
+}
+
+/// Команда `search`: найденные стихотворения всех томов, со всеми языками
+pub fn run(args: &SearchArgs, volumes: &[Volume]) -> Result<()> {
+    let query = Query::new(&args.query, args.mode)?;
+    let mut count = 0;
+    for volume in volumes {
+        let book = &volume.book;
+        let mut poems = book.poems.values().chain(book.incomplete.values()).collect::<Vec<_>>();
+        poems.sort_by_key(|p| p.nn);
+        for poem in poems.into_iter().filter(|p| query.matches_poem(p, &args.langs)) {
+            count += 1;
+            println!("{} ({} {})", poem.nn_str, locale::msg("volume"), book.nn);
+            for (lang, lines) in &poem.text {
+                for (i, line) in lines.iter().enumerate() {
+                    println!("    {:4}{}", if i == 0 { lang.as_str() } else { "" }, line);
+                }
+            }
+            println!();
+        }
+    }
+    println!("{}: {}", locale::msg("found"), count);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::book::tests::get_test_book;
+    use crate::poem::tests::get_test_poem;
+
+    use super::*;
+
+    #[test]
+    fn test_words() {
+        assert_eq!(vec!["еж", "и", "елка"], words("Ёж и «Ёлка»!").collect::<Vec<_>>());
+        assert_eq!(vec!["god", "s", "light"], words("God's light,").collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_index() -> Result<()> {
+        let index = SearchIndex::new(&[Volume::new("Vol. 07.html", get_test_book())])?;
+        assert_eq!("Vol. 07.html", index.volumes[&7]);
+        assert_eq!(7, index.poems[&3]);
+        assert_eq!("Vol. 07.html", index.links[&3]);
+        assert_eq!(7, index.words["ru"]["йцукен"].len());
+        assert!(index.words["en"]["3"].contains(&3));
+        assert!(!index.words["en"].contains_key("йцукен"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_query() -> Result<()> {
+        let poem = get_test_poem(5);
+        let en = vec!["en".to_string()];
+
+        assert!(Query::new("5 qwerty", SearchMode::Words)?.matches_poem(&poem, &[]));
+        assert!(Query::new("ЙЦУКЕН", SearchMode::Words)?.matches_poem(&poem, &[]));
+        assert!(!Query::new("йцукен", SearchMode::Words)?.matches_poem(&poem, &en));
+        assert!(!Query::new("qwerty absent", SearchMode::Words)?.matches_poem(&poem, &[]));
+
+        // Фраза может переходить через конец строки
+        assert!(Query::new("5-1 qwerty 5", SearchMode::Phrase)?.matches_poem(&poem, &[]));
+        assert!(!Query::new("qwerty qwerty", SearchMode::Phrase)?.matches_poem(&poem, &[]));
+
+        // Запрос из одной пунктуации ничего не ищет
+        assert!(matches!(Query::new("—", SearchMode::Words), Err(Error::Usage(_))));
+        assert!(matches!(Query::new("...", SearchMode::Phrase), Err(Error::Usage(_))));
+
+        assert!(Query::new(r"^Qwerty \d-3$", SearchMode::Regex)?.matches_poem(&poem, &en));
+        assert!(Query::new("(", SearchMode::Regex).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Serialize;
+use tera::Context;
+
+use crate::book::Book;
+use crate::collection::Volume;
+use crate::config::CONFIG;
+use crate::locale;
+use crate::template::TEMPLATES;
+use crate::utils::now_iso8601;
+use crate::Result;
+
+/// Имя файла со статусом для внешних панелей внутри `res_dir`
+pub static STATUS_FILE: &str = "status.json";
+
+/// Ход перевода одного тома или всего собрания
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct Progress {
+    /// Все стихотворения, включая неполные
+    pub total: usize,
+    /// Есть все языки из `languages`
+    pub complete: usize,
+    pub incomplete: usize,
+    /// Язык -> неполные стихотворения только на этом языке
+    pub only: BTreeMap<String, usize>,
+    /// Ошибки разбора из отчёта тома
+    pub problems: usize,
+    pub completeness: f64,
+}
+
+impl Progress {
+    fn new(book: &Book, problems: usize) -> Self {
+        let mut only = CONFIG.languages.iter().map(|l| (l.clone(), 0)).collect::<BTreeMap<_, _>>();
+        for poem in book.incomplete.values().filter(|p| p.text.len() == 1) {
+            *only.entry(poem.languages().next().unwrap_or_default().to_string()).or_default() += 1;
+        }
+        Self {
+            total: book.poems.len() + book.incomplete.len(),
+            complete: book.poems.len(),
+            incomplete: book.incomplete.len(),
+            only,
+            problems,
+            completeness: book.completeness(),
+        }
+    }
+
+    fn add(&mut self, other: &Progress) {
+        self.total += other.total;
+        self.complete += other.complete;
+        self.incomplete += other.incomplete;
+        for (lang, count) in &other.only {
+            *self.only.entry(lang.clone()).or_default() += count;
+        }
+        self.problems += other.problems;
+        self.completeness = if self.total == 0 { 0.0 } else { self.complete as f64 * 100.0 / self.total as f64 };
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct VolumeStatus {
+    pub nn: u32,
+    pub link: String,
+    #[serde(flatten)]
+    pub progress: Progress,
+}
+
+/// Ход перевода по томам: страница `status_template`, "status.json" и команда `status`
+#[derive(Serialize, Debug)]
+pub struct Status {
+    pub generated: String,
+    pub languages: Vec<String>,
+    pub volumes: Vec<VolumeStatus>,
+    pub overall: Progress,
+}
+
+impl Status {
+    pub fn new(volumes: &[Volume]) -> Result<Self> {
+        let mut overall = Progress::default();
+        let mut res = Vec::with_capacity(volumes.len());
+        for volume in volumes {
+            let progress = Progress::new(&volume.book, volume.problems);
+            overall.add(&progress);
+            res.push(VolumeStatus { nn: volume.book.nn, link: volume.link()?, progress });
+        }
+        res.sort_by_key(|v| v.nn);
+        Ok(Self { generated: now_iso8601(), languages: CONFIG.languages.clone(), volumes: res, overall })
+    }
+}
+
+/// Контекст `status_template`
+pub fn status_context(status: &Status) -> Context {
+    let mut context = locale::context();
+    context.insert("status", status);
+    context.insert("languages", &CONFIG.languages);
+    context
+}
+
+/// "<res_dir>/status.json" и страница `status_template` -> "<res_dir>/status.html"
+pub fn write_status(res_dir: &Path, volumes: &[Volume]) -> Result<()> {
+    let status = Status::new(volumes)?;
+    std::fs::write(res_dir.join(STATUS_FILE), serde_json::to_string_pretty(&status)?)?;
+    let text = TEMPLATES.render(CONFIG.status_template.as_str(), &status_context(&status))?;
+    std::fs::write(res_dir.join("status.html"), text)?;
+    Ok(())
+}
+
+/// Команда `status`: таблица по томам или, с `json`, тот же JSON, что в "status.json"
+pub fn run(volumes: &[Volume], json: bool) -> Result<()> {
+    let status = Status::new(volumes)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+    let msg = locale::msg;
+    let only = status.overall.only.keys().map(|l| format!(" {:>11}", format!("{} {}", msg("only"), l))).collect::<String>();
+    println!("{:>7} {:>7} {:>11} {:>11}{} {:>9} {:>6}", msg("volume"), msg("total"), msg("translated"), msg("incomplete"), only, msg("problems"), "%");
+    let row = |name: &str, p: &Progress| {
+        let only = p.only.values().map(|c| format!(" {:>11}", c)).collect::<String>();
+        println!("{:>7} {:>7} {:>11} {:>11}{} {:>9} {:>6.1}", name, p.total, p.complete, p.incomplete, only, p.problems, p.completeness);
+    };
+    for volume in &status.volumes {
+        row(&volume.nn.to_string(), &volume.progress);
+    }
+    row(msg("overall"), &status.overall);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::book::tests::get_test_book;
+    use crate::book_builder::BookBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_status() -> Result<()> {
+        let mut book = get_test_book();
+        let mut poem = book.poems.remove(&3).unwrap();
+        poem.text.shift_remove("ru");
+        book.add_incomplete(poem);
+        let mut volume = Volume::new("Vol. 07.html", book);
+        volume.problems = 1;
+
+        let mut book = get_test_book();
+        book.nn = 8;
+        let status = Status::new(&[Volume::new("Vol. 08.html", book), volume])?;
+        assert_eq!(vec![7, 8], status.volumes.iter().map(|v| v.nn).collect::<Vec<_>>());
+
+        let p = &status.volumes[0].progress;
+        assert_eq!((7, 6, 1, 1), (p.total, p.complete, p.incomplete, p.problems));
+        assert_eq!(1, p.only["en"]);
+        assert_eq!(0, p.only["ru"]);
+
+        let p = &status.overall;
+        assert_eq!((14, 13, 1, 1), (p.total, p.complete, p.incomplete, p.problems));
+        assert_eq!(1, p.only["en"]);
+        assert!((p.completeness - 1300.0 / 14.0).abs() < 1e-9);
+
+        let json = serde_json::to_value(&status)?;
+        assert_eq!(7, json["volumes"][0]["nn"]);
+        assert_eq!(6, json["volumes"][0]["complete"]);
+
+        let text = TEMPLATES.render(CONFIG.status_template.as_str(), &status_context(&status))?;
+        assert!(text.contains(r#"<a href="Vol.%2007.html">7</a>"#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_incomplete_is_not_a_problem() -> Result<()> {
+        let mut builder = BookBuilder::with_languages(7, vec!["en".to_string(), "ru".to_string()]);
+        for nn in 1..=2 {
+            builder.proc_number(nn);
+            builder.proc_line(format!("Qwerty {}", nn));
+        }
+        builder.proc_number(1);
+        builder.proc_line(String::from("Йцукен 1"));
+        let (book, report) = builder.build();
+        assert_eq!(1, report.errors().len());
+        let volume = Volume { problems: report.problems(), ..Volume::new("Vol. 07.html", book.unwrap()) };
+
+        let status = Status::new(&[volume])?;
+        let p = &status.volumes[0].progress;
+        assert_eq!((2, 1, 1, 0), (p.total, p.complete, p.incomplete, p.problems));
+        Ok(())
+    }
+}
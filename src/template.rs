@@ -6,30 +6,99 @@
 extern crate lazy_static;//#[macro_use]
 extern crate tera;
 
+use std::fs;
+use std::path::{Path, PathBuf};
+
 use lazy_static::lazy_static;
 use tera::Tera;
 use crate::config::CONFIG;
+use crate::filters;
+use crate::Result;
 
 lazy_static! {
     pub static ref TEMPLATES: Tera = {
-        let tera = match Tera::new(CONFIG.template_pattern.as_str()) {
+        match load_templates() {
             Ok(t) => t,
             Err(e) => {
                 println!("Parsing templates error(s): {:?}", e);
                 ::std::process::exit(1);
             }
-        };
-        // tera.autoescape_on(vec![".html", ".sql"]);
-        // tera.register_filter("do_nothing", do_nothing_filter);
-        tera
+        }
     };
 }
 
+/// Шаблоны по умолчанию, встроенные в программу: имя -> текст
+pub static DEFAULT_TEMPLATES: [(&str, &str); 13] = [
+    ("base.html", include_str!("../templates/base.html")),
+    ("poems_77000.html", include_str!("../templates/poems_77000.html")),
+    ("problems.html", include_str!("../templates/problems.html")),
+    ("volume_nav.html", include_str!("../templates/volume_nav.html")),
+    ("page_nav.html", include_str!("../templates/page_nav.html")),
+    ("index.html", include_str!("../templates/index.html")),
+    ("numbers.html", include_str!("../templates/numbers.html")),
+    ("search.html", include_str!("../templates/search.html")),
+    ("status.html", include_str!("../templates/status.html")),
+    ("epub/content.opf", include_str!("../templates/epub/content.opf")),
+    ("epub/nav.xhtml", include_str!("../templates/epub/nav.xhtml")),
+    ("epub/style.css", include_str!("../templates/epub/style.css")),
+    ("epub/volume.xhtml", include_str!("../templates/epub/volume.xhtml")),
+];
+
+/// Шаблоны из `template_pattern` со всеми настройками и фильтрами;
+/// недостающие берутся из встроенных, одноимённые пользовательские их заменяют
+pub fn load_templates() -> tera::Result<Tera> {
+    load_templates_from(CONFIG.template_pattern.as_str())
+}
+
+fn load_templates_from(pattern: &str) -> tera::Result<Tera> {
+    // `parse`, а не `new`: пользовательский шаблон может наследовать встроенный
+    let mut tera = Tera::parse(pattern)?;
+    let mut defaults = Tera::default();
+    defaults.add_raw_templates(DEFAULT_TEMPLATES)?;
+    tera.extend(&defaults)?;
+    // tera.autoescape_on(vec![".html", ".sql"]);
+    tera.autoescape_on(vec![".html", ".htm", ".xml", ".xhtml", ".opf"]);
+    // tera.register_filter("do_nothing", do_nothing_filter);
+    tera.register_filter("poem_number", filters::poem_number);
+    tera.register_filter("roman", filters::roman);
+    tera.register_filter("volume_range", filters::volume_range);
+    tera.register_filter("lines_to_html", filters::LinesToHtml);
+    tera.register_filter("rich_text", filters::RichText);
+    tera.register_function("poem", filters::PoemLookup);
+    Ok(tera)
+}
+
+/// Каталог шаблонов: часть `template_pattern` до первого `*`, "templates/**/*" -> "templates/"
+pub fn template_dir(pattern: &str) -> PathBuf {
+    let prefix = &pattern[..pattern.find('*').unwrap_or(pattern.len())];
+    match prefix.rfind('/') {
+        Some(i) => PathBuf::from(&prefix[..=i]),
+        None => PathBuf::from("."),
+    }
+}
+
+/// Команда `init`: встроенные файлы (шаблоны, сообщения) в каталог `dir` для правки;
+/// существующие файлы заменяются только с `force`
+pub fn write_defaults(dir: &Path, files: &[(&str, &str)], force: bool) -> Result<()> {
+    for (name, text) in files {
+        let path = dir.join(name);
+        if path.exists() && !force {
+            println!("skip     {}", path.display());
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, text)?;
+        println!("write    {}", path.display());
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use tera::Context;
-
     use crate::book::tests::get_test_book;
+    use crate::locale;
 
     use super::*;
 
@@ -38,11 +107,41 @@ mod tests {
         let book = get_test_book();
         //let l: Vec<&str> = TEMPLATES.get_template_names().collect();
         println!("qqq{:?}", TEMPLATES.get_template_names().collect::<Vec<&str>>());
-        let mut context = Context::new();
+        let mut context = locale::context();
         context.insert("book", &book);
         context.insert("books", &book.get_ordered_poems());
+        crate::render::insert_blocks(&mut context, &book);
+        context.insert("languages", &CONFIG.languages);
         // let res = TEMPLATES.render("poems_77000.html", &context);
         let res = TEMPLATES.render(CONFIG.poem_template.as_str(), &context);
         println!("{}", res.unwrap());
     }
+
+    #[test]
+    fn test_defaults() -> Result<()> {
+        assert_eq!(PathBuf::from("templates/"), template_dir("templates/**/*"));
+        assert_eq!(PathBuf::from("a/b/"), template_dir("a/b/*.html"));
+        assert_eq!(PathBuf::from("."), template_dir("*.html"));
+
+        let dir = std::env::temp_dir().join("html-77000-init-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("base.html"), "{% block content %}{% endblock content %}")?;
+
+        // Пользовательский шаблон заменяет встроенный, остальные остаются встроенными
+        let tera = load_templates_from(&format!("{}/*", dir.display()))?;
+        assert!(tera.get_template("base.html")?.path.is_some());
+        assert!(tera.get_template("problems.html")?.path.is_none());
+
+        let default = |name: &str| DEFAULT_TEMPLATES.iter().find(|(n, _)| *n == name).unwrap().1;
+        write_defaults(&dir, &DEFAULT_TEMPLATES, false)?;
+        assert_eq!("{% block content %}{% endblock content %}", fs::read_to_string(dir.join("base.html"))?);
+        // Шаблон во вложенном каталоге
+        assert_eq!(default("epub/style.css"), fs::read_to_string(dir.join("epub/style.css"))?);
+
+        write_defaults(&dir, &DEFAULT_TEMPLATES, true)?;
+        assert_eq!(default("base.html"), fs::read_to_string(dir.join("base.html"))?);
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
 }
\ No newline at end of file
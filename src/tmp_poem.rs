@@ -1,5 +1,9 @@
 pub struct TmpPoem {
     pub nn: u32,
+    /// Язык, явно заданный маркером (классом абзаца); иначе определяется по порядку
+    pub lang: Option<String>,
+    /// Название после номера
+    pub title: Option<String>,
     pub lines: Vec<String>,
 }
 
@@ -7,6 +11,8 @@ impl TmpPoem {
     pub fn new(nn: u32) -> Self {
         Self {
             nn,
+            lang: None,
+            title: None,
             lines: vec![],
         }
     }
@@ -14,4 +20,4 @@ impl TmpPoem {
     pub fn add_line(&mut self, line: String) {
         self.lines.push(line)
     }
-}
\ No newline at end of file
+}
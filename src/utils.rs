@@ -1,27 +1,34 @@
 use lazy_static::lazy_static;
+use std::borrow::Cow;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 use regex::Regex;
-use std::ops::Deref;
+use scraper::{ElementRef, Node};
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
+use crate::config::CONFIG;
 use crate::{Error,Result};
 
 lazy_static! {
     static ref RE_DD: Regex = Regex::new(r"\d\d").unwrap();
-    static ref RE_SPEC:Regex = Regex::new(r"&.*?;").unwrap();
-    static ref RE_NON_DIGIT:Regex = Regex::new(r"\D+").unwrap();
-    static ref RE_TAGS:Regex = Regex::new(r"<.*?>").unwrap();
+    /// Номер (группы по три цифры через запятую или пробел), точка, знак сноски, название
+    static ref RE_POEM_NUM:Regex = Regex::new(
+        r"^\s*(?P<num>\d+(?:(?:,\s*|\s+)\d{3})*)\b\.?[*†‡¹²³⁰-⁹\d]*(?:\s+(?P<title>.*\S))?\s*$"
+    ).unwrap();
+    static ref RE_RICH_TAGS:Regex = rich_tags_regex(&CONFIG.rich_text_tags);
+    static ref RE_PLACEHOLDER:Regex = Regex::new(r"\{(\w+)(?::0(\d+))?\}").unwrap();
 }
 
 pub fn join_file_path(base_dir_name: &str, file_name: &str) -> PathBuf {
     Path::new(base_dir_name).join(file_name)
 }
-pub fn init_logger() {
+pub fn init_logger(level: Level) {
     // a builder for `FmtSubscriber`.
     let subscriber = FmtSubscriber::builder()
-        // all spans/events with a level higher than TRACE (e.g, debug, info, warn, etc.)
+        // all spans/events with a level higher than `level` (e.g, debug, info, warn, etc.)
         // will be written to stdout.
-        .with_max_level(Level::TRACE)
+        .with_max_level(level)
         // completes the builder.
         .finish();
 
@@ -29,29 +36,166 @@ pub fn init_logger() {
         .expect("setting default subscriber failed");    // a builder for `FmtSubscriber`.
 }
 
-pub fn path_2_str<'a>(pb: &'a PathBuf)->Result<&'a str>{
-    Ok(
-        pb.file_name().map(|s|s.to_str()).flatten()
-            .ok_or_else(|| 
-                Error::PathError{
-                    path: pb.as_os_str().to_string_lossy().into()
-                })?
-    )
+pub fn path_2_str(pb: &Path)->Result<&str>{
+    pb.file_name().and_then(|s|s.to_str())
+        .ok_or_else(|| 
+            Error::PathError{
+                path: pb.as_os_str().to_string_lossy().into()
+            })
 }
 
-/// "01&#160;000." -> 1000
-/// "03,456." -> 3456
-pub fn parse_poem_num_impl(str: &str) -> Result<u32> {
-    let r1 = RE_SPEC.replace_all(str, "");
-    let binding = RE_NON_DIGIT.replace_all(r1.deref(), "");
-    let x = binding.deref();
-    // let len = str.len();
-    // let x = format!("{}{}",str[0..2].to_string(), str[len - 4..len - 1].to_string());
-    Ok(x.parse::<u32>()?)
+/// Номер стихотворения из абзаца номера, с названием после него, если оно есть
+#[derive(Debug, PartialEq)]
+pub struct PoemNumber {
+    pub nn: u32,
+    pub title: Option<String>,
 }
 
-pub fn re_remove_tags(line: &str) -> String {
-    RE_TAGS.replace_all(line, "").deref().to_string()
+/// Текст абзаца номера (сущности раскодированы):
+/// "01\u{a0}000." -> 1000, "03,456." -> 3456, "03,456.* (Посвящение)" -> 3456 и "(Посвящение)";
+/// всё остальное, в том числе номер без цифр или 0, - ошибка `BadPoemNumber`
+pub fn parse_poem_num_impl(str: &str) -> Result<PoemNumber> {
+    let bad = || Error::BadPoemNumber { text: str.to_string() };
+    let caps = RE_POEM_NUM.captures(str).ok_or_else(bad)?;
+    let digits = caps["num"].chars().filter(char::is_ascii_digit).collect::<String>();
+    let nn = digits.parse::<u32>().map_err(|_| bad())?;
+    if nn == 0 {
+        return Err(bad());
+    }
+    Ok(PoemNumber { nn, title: caps.name("title").map(|t| t.as_str().to_string()) })
+}
+
+/// Текст абзаца без разметки (сущности раскодированы); теги из `rich_tags`
+/// сохраняются без атрибутов, а `&`, `<` и `>` в тексте тогда снова экранируются,
+/// чтобы текст "&lt;i&gt;" не стал тегом: `<i class="x">a &amp; b</i>` -> `<i>a &amp; b</i>`.
+/// `<br>` и границы блочных элементов становятся пробелом: `06,005.<br>(Посвящение)`
+/// -> "06,005. (Посвящение)"
+pub fn line_text(element: &ElementRef, rich_tags: &[String]) -> String {
+    let mut res = String::new();
+    let mut sep = false;
+    push_text(element, rich_tags, &mut res, &mut sep);
+    res
+}
+
+/// Пробел на месте разрыва, если он не в начале или в конце строки и не рядом с другим пробелом
+fn push_sep(res: &mut String, sep: &mut bool, next: &str) {
+    if std::mem::take(sep)
+        && !res.is_empty()
+        && !res.ends_with(char::is_whitespace)
+        && !next.starts_with(char::is_whitespace) {
+        res.push(' ');
+    }
+}
+
+fn push_text(element: &ElementRef, rich_tags: &[String], res: &mut String, sep: &mut bool) {
+    for node in element.children() {
+        match node.value() {
+            Node::Text(text) if !text.is_empty() => {
+                push_sep(res, sep, text);
+                if rich_tags.is_empty() {
+                    res.push_str(text);
+                } else {
+                    res.push_str(&escape_text(text));
+                }
+            }
+            Node::Element(e) => {
+                let Some(child) = ElementRef::wrap(node) else { continue };
+                // Содержимое скриптов и стилей - не текст стихотворения
+                if matches!(e.name(), "script" | "style") {
+                    continue;
+                }
+                let block = matches!(e.name(), "br" | "p" | "div" | "li" | "blockquote"
+                    | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "tr" | "td" | "th");
+                *sep |= block;
+                let keep = rich_tags.iter().any(|t| t == e.name());
+                if keep {
+                    push_sep(res, sep, "");
+                    res.push_str(&format!("<{}>", e.name()));
+                }
+                push_text(&child, rich_tags, res, sep);
+                if keep {
+                    res.push_str(&format!("</{}>", e.name()));
+                }
+                *sep |= block;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Экранирование текста строки с тегами форматирования
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Обратно к `escape_text`
+fn unescape_text(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+}
+
+/// Разрешённые теги форматирования, без атрибутов: `<i>`, `</i>`, ...
+fn rich_tags_regex(tags: &[String]) -> Regex {
+    Regex::new(&format!(
+        r"<(/?)({})>",
+        tags.iter().map(|t| regex::escape(t)).collect::<Vec<_>>().join("|")
+    )).unwrap()
+}
+
+/// Строка для HTML: всё экранировано, кроме разрешённых тегов форматирования (`rich_text_tags`;
+/// текст между ними уже экранирован `line_text`);
+/// лишние закрывающие теги отбрасываются, незакрытые - закрываются в конце строки
+pub fn sanitize_rich_text(line: &str) -> String {
+    if CONFIG.rich_text_tags.is_empty() {
+        return tera::escape_html(line);
+    }
+    sanitize_with(line, &RE_RICH_TAGS)
+}
+
+fn sanitize_with(line: &str, re_tags: &Regex) -> String {
+    let mut res = String::with_capacity(line.len());
+    let mut open: Vec<&str> = vec![];
+    let mut last = 0;
+    for caps in re_tags.captures_iter(line) {
+        let m = caps.get(0).unwrap();
+        res.push_str(&tera::escape_html(&unescape_text(&line[last..m.start()])));
+        last = m.end();
+        let tag = caps.get(2).unwrap().as_str();
+        if caps[1].is_empty() {
+            open.push(tag);
+            res.push_str(&format!("<{}>", tag));
+        } else if let Some(i) = open.iter().rposition(|t| *t == tag) {
+            // Закрываем и всё, что открыто внутри
+            for t in open.drain(i..).rev() {
+                res.push_str(&format!("</{}>", t));
+            }
+        }
+    }
+    res.push_str(&tera::escape_html(&unescape_text(&line[last..])));
+    for t in open.into_iter().rev() {
+        res.push_str(&format!("</{}>", t));
+    }
+    res
+}
+
+/// Положение тегов форматирования (`rich_text_tags`) в строке, в байтах; остальное - текст
+pub fn rich_tag_ranges(line: &str) -> Vec<Range<usize>> {
+    if CONFIG.rich_text_tags.is_empty() {
+        return Vec::new();
+    }
+    RE_RICH_TAGS.find_iter(line).map(|m| m.range()).collect()
+}
+
+/// Строка без разрешённых тегов форматирования (`rich_text_tags`) и экранирования -
+/// для текстовых форматов и поиска
+pub fn plain_text(line: &str) -> Cow<'_, str> {
+    if CONFIG.rich_text_tags.is_empty() {
+        return Cow::Borrowed(line);
+    }
+    plain_with(line, &RE_RICH_TAGS)
+}
+
+fn plain_with<'a>(line: &'a str, re_tags: &Regex) -> Cow<'a, str> {
+    Cow::Owned(unescape_text(&re_tags.replace_all(line, "")))
 }
 
 /// "Vol.07.html" -> 7
@@ -66,16 +210,162 @@ pub fn parse_book_num(name: &str) -> Result<u32> {
     }
 }
 
+/// "print/{volume:02}.{ext}" -> "print/07.html"
+///
+/// `{volume}` - номер тома (`{volume:02}` - дополненный нулями),
+/// `{source}` - имя исходного файла без расширения, `{ext}` - расширение формата
+pub fn format_file_name(pattern: &str, volume: u32, source: &str, ext: &str) -> Result<String> {
+    let mut res = String::with_capacity(pattern.len());
+    let mut last = 0;
+    for caps in RE_PLACEHOLDER.captures_iter(pattern) {
+        let m = caps.get(0).unwrap();
+        res.push_str(&pattern[last..m.start()]);
+        let width = caps.get(2).map(|w| w.as_str().parse::<usize>()).transpose()?;
+        match (&caps[1], width) {
+            ("volume", Some(width)) => res.push_str(&format!("{:0width$}", volume, width = width)),
+            ("volume", None) => res.push_str(&volume.to_string()),
+            ("source", None) => res.push_str(source),
+            ("ext", None) => res.push_str(ext),
+            _ => return Err(Error::BadFileNamePattern{ pattern: pattern.into() }),
+        }
+        last = m.end();
+    }
+    res.push_str(&pattern[last..]);
+    if res.contains(['{', '}']) {
+        return Err(Error::BadFileNamePattern{ pattern: pattern.into() });
+    }
+    Ok(res)
+}
+
+/// Файл страницы `page` (с 1) выхода `file_name`: ("web/07.html", 2) -> "web/07-2.html"
+pub fn page_file_name(file_name: &str, page: usize) -> String {
+    if page <= 1 {
+        return file_name.to_string();
+    }
+    let start = file_name.rfind('/').map_or(0, |i| i + 1);
+    match file_name[start..].rfind('.') {
+        Some(i) if i > 0 => format!("{}-{}{}", &file_name[..start + i], page, &file_name[start + i..]),
+        _ => format!("{}-{}", file_name, page),
+    }
+}
+
+/// Ссылка из файла `from` на файл `to`, оба пути относительно одного каталога:
+/// ("web/07.html", "web/08.html") -> "08.html", ("index.html", "web/08.html") -> "web/08.html"
+pub fn relative_link(from: &str, to: &str) -> String {
+    let from = from.split('/').collect::<Vec<_>>();
+    let to = to.split('/').collect::<Vec<_>>();
+    let from_dir = &from[..from.len() - 1];
+    let common = from_dir.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let common = common.min(to.len() - 1);
+    let mut res = vec![".."; from_dir.len() - common];
+    res.extend(&to[common..]);
+    res.join("/")
+}
+
+/// Текущее время UTC в виде "2024-05-17T09:30:00Z"
+pub fn now_iso8601() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    format_iso8601(secs)
+}
+
+/// Секунды от начала эпохи -> "YYYY-MM-DDThh:mm:ssZ" (алгоритм civil_from_days)
+fn format_iso8601(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year, month, day, rem / 3_600, rem % 3_600 / 60, rem % 60)
+}
+
 #[cfg(test)]
 mod tests {
-    use std::path::Path;
 
     use super::*;
 
     #[test]
     fn test_poem_num() -> Result<()> {
-        assert_eq!(3456, parse_poem_num_impl("03,456.")?);
-        assert_eq!(1000, parse_poem_num_impl("01&#160;000.")?);
+        let num = |nn, title: Option<&str>| PoemNumber { nn, title: title.map(String::from) };
+        assert_eq!(num(3456, None), parse_poem_num_impl("03,456.")?);
+        assert_eq!(num(1000, None), parse_poem_num_impl("01\u{a0}000.")?);
+        assert_eq!(num(1000, None), parse_poem_num_impl(" 01,\n000. ")?);
+        assert_eq!(num(1000, None), parse_poem_num_impl("1000")?);
+        assert_eq!(num(1234, Some("(Dedication)")), parse_poem_num_impl("01,234. (Dedication)")?);
+        // Знак сноски вплотную к номеру - не цифры номера
+        assert_eq!(num(1234, None), parse_poem_num_impl("01,234.1")?);
+        assert_eq!(num(1234, Some("Title")), parse_poem_num_impl("01,234.* Title")?);
+
+        for bad in ["", ".", "No number", "01,2345.", "01,23.", "00,000.", "01,234.x", "99999999999"] {
+            assert!(matches!(parse_poem_num_impl(bad), Err(Error::BadPoemNumber { .. })), "{:?}", bad);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_sanitize_rich_text() {
+        let re = rich_tags_regex(&["i".to_string(), "b".to_string()]);
+        assert_eq!("<i>a &amp; b</i>", sanitize_with("<i>a &amp; b</i>", &re));
+        assert_eq!("&lt;script&gt;x&lt;&#x2F;script&gt;", sanitize_with("&lt;script&gt;x&lt;/script&gt;", &re));
+        assert_eq!("&lt;i class=&quot;x&quot;&gt;y", sanitize_with(r#"&lt;i class="x"&gt;y"#, &re));
+        // Незакрытые закрываются, лишние закрывающие отбрасываются
+        assert_eq!("<b><i>x</i></b>y", sanitize_with("<b><i>x</b>y</i>", &re));
+        assert_eq!("<i>x</i>", sanitize_with("<i>x", &re));
+    }
+
+    #[test]
+    fn test_line_text() {
+        let html = scraper::Html::parse_fragment(
+            r#"<p>a &lt;b&gt; <i class="x">&amp; <span>c</span></i><script>alert(1)</script></p>"#);
+        let p = html.select(&scraper::Selector::parse("p").unwrap()).next().unwrap();
+        assert_eq!("a <b> & c", line_text(&p, &[]));
+        assert_eq!("a &lt;b&gt; <i>&amp; c</i>", line_text(&p, &["i".to_string()]));
+
+        // Текст "<i>" в источнике остаётся текстом, а не тегом
+        let html = scraper::Html::parse_fragment(r#"<p>Write &lt;i&gt; to <i>italicise</i></p>"#);
+        let p = html.select(&scraper::Selector::parse("p").unwrap()).next().unwrap();
+        let line = line_text(&p, &["i".to_string()]);
+        let re = rich_tags_regex(&["i".to_string()]);
+        assert_eq!("Write &lt;i&gt; to <i>italicise</i>", sanitize_with(&line, &re));
+        assert_eq!("Write <i> to italicise", plain_with(&line, &re));
+
+        let html = scraper::Html::parse_fragment(
+            r#"<div><br>06,005.<br/>(Dedication) <p>x</p><span>y</span><i>z</i><br></div>"#);
+        let p = html.select(&scraper::Selector::parse("div").unwrap()).next().unwrap();
+        assert_eq!("06,005. (Dedication) x yz", line_text(&p, &[]));
+    }
+
+    #[test]
+    fn test_file_name_pattern() -> Result<()> {
+        assert_eq!("07.html", format_file_name("{volume:02}.html", 7, "Vol. 07", "html")?);
+        assert_eq!("print/7.html", format_file_name("print/{volume}.html", 7, "Vol. 07", "html")?);
+        assert_eq!("Vol. 07.md", format_file_name("{source}.{ext}", 7, "Vol. 07", "md")?);
+        assert!(format_file_name("{book}.html", 7, "Vol. 07", "html").is_err());
+        assert!(format_file_name("{volume.html", 7, "Vol. 07", "html").is_err());
+
+        assert_eq!("Vol. 07.html", page_file_name("Vol. 07.html", 1));
+        assert_eq!("Vol. 07-2.html", page_file_name("Vol. 07.html", 2));
+        assert_eq!("web.d/07-3", page_file_name("web.d/07", 3));
         Ok(())
     }
+
+    #[test]
+    fn test_relative_link() {
+        assert_eq!("08.html", relative_link("web/07.html", "web/08.html"));
+        assert_eq!("web/08.html", relative_link("index.html", "web/08.html"));
+        assert_eq!("../08/index.html", relative_link("07/index.html", "08/index.html"));
+        assert_eq!("../index.html", relative_link("web/07.html", "index.html"));
+    }
+
+    #[test]
+    fn test_iso8601() {
+        assert_eq!("1970-01-01T00:00:00Z", format_iso8601(0));
+        assert_eq!("2024-02-29T13:05:09Z", format_iso8601(1_709_211_909));
+    }
 }
\ No newline at end of file